@@ -0,0 +1,144 @@
+// src/blocks.rs
+
+use crate::note::{ParsedNote, Spanned};
+use crate::position::LineIndex;
+use regex::Regex;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::ops;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_lsp::lsp_types::Position;
+
+/// A `^block-id` marker at the end of a paragraph or list item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockId {
+    /// Zero-based line index of the marker.
+    pub line: usize,
+    /// Byte offset of the `^`.
+    pub start: usize,
+    /// Byte offset just past the id.
+    pub end: usize,
+    /// The id, without the `^`.
+    pub id: String,
+}
+
+fn block_id_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:^|\s)\^([A-Za-z0-9-]+)\s*$").unwrap())
+}
+
+/// Returns the block ids declared in a note, line by line; `lines` is the line index of `text`.
+/// [`ParsedNote::block_ids`] holds the ones outside code and the frontmatter, which are the only
+/// ones that count.
+pub fn find_block_ids(text: &str, lines: &LineIndex) -> Vec<BlockId> {
+    let mut ids = Vec::new();
+    for (line_index, line) in lines.lines(text).enumerate() {
+        if let Some(caps) = block_id_regex().captures(line) {
+            let id = caps.get(1).unwrap();
+            ids.push(BlockId {
+                line: line_index,
+                start: id.start() - 1,
+                end: id.end(),
+                id: id.as_str().to_string(),
+            });
+        }
+    }
+    ids
+}
+
+/// Finds the block a `#^block-id` anchor points at.
+pub fn find_block<'a>(note: &'a ParsedNote, id: &str) -> Option<&'a BlockId> {
+    note.block_ids.iter().map(|b| &b.value).find(|b| b.id == id)
+}
+
+/// Whether a marker stands on a line of its own.
+pub fn is_standalone(note: &ParsedNote, block: &BlockId) -> bool {
+    note.line(block.line)
+        .is_some_and(|line| line[..block.start.min(line.len())].trim().is_empty())
+}
+
+/// Byte range of the innermost paragraph or list item covering `line`, up to its last line with
+/// content. A list item ends before the items nested in it. Headings, tables, code, the
+/// frontmatter and blank lines are not part of any block.
+pub fn block_at(note: &ParsedNote, line: usize) -> Option<ops::Range<usize>> {
+    let text = note.line(line)?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    let start = note.offset(Position::new(line as u32, 0));
+    let end = start + text.len();
+    let block = note
+        .paragraphs
+        .iter()
+        .chain(&note.list_items)
+        .filter(|span| span.start < end && start < span.end)
+        .min_by_key(|span| span.len())?;
+    let block_end = note
+        .list_items
+        .iter()
+        .filter(|item| block.start < item.start && item.start < block.end)
+        .map(|item| item.start)
+        .min()
+        .unwrap_or(block.end);
+    Some(block.start..note.text[..block_end].trim_end().len())
+}
+
+/// Returns the first and last line of the block a marker belongs to. A marker on a line of its
+/// own refers to the block right above it (e.g. a list item or a paragraph).
+pub fn block_span(note: &ParsedNote, block: &BlockId) -> (usize, usize) {
+    let above = block
+        .line
+        .checked_sub(1)
+        .filter(|_| is_standalone(note, block));
+    above
+        .and_then(|line| block_at(note, line))
+        .or_else(|| block_at(note, block.line))
+        .map(|span| {
+            let range = note.range(&span);
+            (range.start.line as usize, range.end.line as usize)
+        })
+        .unwrap_or((block.line, block.line))
+}
+
+/// Returns the lines of the block a marker belongs to, without the marker itself.
+pub fn block_lines<'a>(note: &'a ParsedNote, block: &BlockId) -> Vec<&'a str> {
+    let (start, end) = block_span(note, block);
+    let mut lines: Vec<&str> = (start..=end).filter_map(|i| note.line(i)).collect();
+    if end == block.line {
+        if is_standalone(note, block) && start < end {
+            lines.pop();
+        } else if let Some(last) = lines.last_mut() {
+            *last = last[..block.start.min(last.len())].trim_end();
+        }
+    }
+    lines
+}
+
+/// Generates a short block id that is not used in the note yet.
+pub fn generate_block_id(existing: &[Spanned<BlockId>]) -> String {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let mut attempt: u64 = 0;
+    loop {
+        // RandomState is seeded randomly per instance, which is all the entropy we need here.
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        hasher.write_u64(attempt);
+        let mut value = hasher.finish();
+        let id: String = (0..6)
+            .map(|_| {
+                let c = ALPHABET[(value % ALPHABET.len() as u64) as usize] as char;
+                value /= ALPHABET.len() as u64;
+                c
+            })
+            .collect();
+        if !existing.iter().any(|b| b.value.id == id) {
+            return id;
+        }
+        attempt += 1;
+    }
+}
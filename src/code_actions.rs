@@ -0,0 +1,188 @@
+// src/code_actions.rs
+
+use crate::blocks;
+use crate::note::ParsedNote;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, Command, Diagnostic, Range, TextEdit, WorkspaceEdit,
+};
+use url::Url;
+
+/// Command run after a block reference action: hands the ready-made link back to the client.
+pub const COPY_BLOCK_LINK: &str = "gnosis.copyBlockLink";
+/// Command creating the note of an unresolved wiki-link. Arguments: virtual path, title.
+pub const CREATE_NOTE: &str = "gnosis.createNote";
+
+/// Offers a link to the paragraph or list item at `line`. When the block has no `^block-id`
+/// yet, the action appends a fresh one; in both cases it then runs [`COPY_BLOCK_LINK`] with a
+/// `[[virtual_path#^id]]` link. Headings are linked with `#Heading` anchors instead, and code
+/// and the frontmatter are not blocks.
+pub fn block_reference_action(
+    uri: &Url,
+    note: &ParsedNote,
+    line: usize,
+    virtual_path: &str,
+) -> Option<CodeAction> {
+    let block = blocks::block_at(note, line)?;
+    let end = note.range(&block).end;
+    let end_line = end.line as usize;
+    let current =
+        note.block_ids.iter().map(|b| &b.value).find(|b| {
+            b.line == end_line || (b.line == end_line + 1 && blocks::is_standalone(note, b))
+        });
+
+    let (title, id, edit) = match current {
+        Some(block) => (
+            format!("Copy link to block ^{}", block.id),
+            block.id.clone(),
+            None,
+        ),
+        None => {
+            let id = blocks::generate_block_id(&note.block_ids);
+            let edit = TextEdit {
+                range: Range { start: end, end },
+                new_text: format!(" ^{}", id),
+            };
+            (
+                format!("Add block id ^{} and copy link", id),
+                id,
+                Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                    ..Default::default()
+                }),
+            )
+        }
+    };
+
+    let link = format!("[[{}#^{}]]", virtual_path, id);
+    Some(CodeAction {
+        title,
+        kind: Some(CodeActionKind::REFACTOR),
+        edit,
+        command: Some(Command {
+            title: "Copy block link".to_string(),
+            command: COPY_BLOCK_LINK.to_string(),
+            arguments: Some(vec![Value::String(link)]),
+        }),
+        ..Default::default()
+    })
+}
+
+/// Offers to create the missing note of an unresolved wiki-link. The note is created by the
+/// [`CREATE_NOTE`] command so that it can also be registered in the database.
+/// `diagnostics` are the broken-link diagnostics this action fixes.
+pub fn create_note_action(
+    virtual_path: &str,
+    alias: Option<&str>,
+    diagnostics: Vec<Diagnostic>,
+) -> CodeAction {
+    let title = note_title(virtual_path, alias);
+    CodeAction {
+        title: format!("Create note for [[{}]]", virtual_path),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: (!diagnostics.is_empty()).then_some(diagnostics),
+        is_preferred: Some(true),
+        command: Some(Command {
+            title: "Create note".to_string(),
+            command: CREATE_NOTE.to_string(),
+            arguments: Some(vec![
+                Value::String(virtual_path.to_string()),
+                Value::String(title),
+            ]),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Title of a new note: the link alias when there is one, otherwise the last segment of the
+/// virtual path.
+fn note_title(virtual_path: &str, alias: Option<&str>) -> String {
+    match alias.filter(|a| !a.is_empty()) {
+        Some(alias) => alias.to_string(),
+        None => virtual_path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(virtual_path)
+            .to_string(),
+    }
+}
+
+/// Local path of a new note: the virtual path taken relative to the workspace root. Returns
+/// `None` when the virtual path would leave the workspace, e.g. `../notes/x`.
+pub fn new_note_path(workspace_root: &Path, virtual_path: &str) -> Option<PathBuf> {
+    stays_in_workspace(virtual_path)
+        .then(|| workspace_root.join(format!("{}.md", virtual_path.trim_matches('/'))))
+}
+
+/// Whether a virtual path, taken as a path relative to the workspace root, names a file below
+/// it: every segment is a plain name, without `..`, `.` or a drive prefix.
+pub fn stays_in_workspace(virtual_path: &str) -> bool {
+    Path::new(&format!("{}.md", virtual_path.trim_matches('/')))
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::PositionEncoding;
+    use tower_lsp::lsp_types::Position;
+
+    fn block_action(text: &str, line: usize) -> Option<CodeAction> {
+        let note = ParsedNote::parse(text, PositionEncoding::Utf16);
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        block_reference_action(&uri, &note, line, "note")
+    }
+
+    fn added_id(action: &CodeAction) -> TextEdit {
+        let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        changes.values().next().unwrap()[0].clone()
+    }
+
+    #[test]
+    fn block_ids_are_appended_to_the_last_line_of_the_block() {
+        let action = block_action("First line\nsecond line\n\nOther\n", 0).unwrap();
+        let edit = added_id(&action);
+        assert_eq!(edit.range.start, Position::new(1, 11));
+        assert!(edit.new_text.starts_with(" ^"));
+    }
+
+    #[test]
+    fn list_items_end_before_their_nested_items() {
+        let text = "- parent\n  - child\n- next\n";
+        let edit = added_id(&block_action(text, 0).unwrap());
+        assert_eq!(edit.range.start, Position::new(0, 8));
+        let edit = added_id(&block_action(text, 1).unwrap());
+        assert_eq!(edit.range.start, Position::new(1, 9));
+    }
+
+    #[test]
+    fn existing_block_ids_are_reused() {
+        let action = block_action("Some text ^abc123\n", 0).unwrap();
+        assert!(action.edit.is_none());
+        assert_eq!(action.title, "Copy link to block ^abc123");
+    }
+
+    #[test]
+    fn code_headings_and_frontmatter_are_not_blocks() {
+        let text = "---\ntitle: x\n---\n# Heading\n\n~~~\n```\ncode\n~~~\n";
+        for line in 0..text.lines().count() {
+            assert!(block_action(text, line).is_none(), "line {}", line);
+        }
+    }
+
+    #[test]
+    fn new_notes_stay_inside_the_workspace() {
+        let root = Path::new("/vault");
+        assert_eq!(
+            new_note_path(root, "/projects/idea"),
+            Some(PathBuf::from("/vault/projects/idea.md"))
+        );
+        assert_eq!(new_note_path(root, "../../../tmp/x"), None);
+        assert_eq!(new_note_path(root, "notes/../../x"), None);
+        assert_eq!(new_note_path(root, "./x"), None);
+    }
+}
@@ -0,0 +1,340 @@
+// src/completion.rs
+
+use crate::blocks;
+use crate::config::HoverConfig;
+use crate::db;
+use crate::hover_preview;
+use crate::link_references::HybridIndex;
+use crate::note::ParsedNote;
+use crate::note_index::IndexedNote;
+use crate::position::PositionEncoding;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use log::error;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionList, CompletionTextEdit, Documentation,
+    MarkupContent, MarkupKind, Range, TextEdit,
+};
+use url::Url;
+
+/// Maximum number of notes returned by one completion request. When more notes match, the list
+/// is marked incomplete and the client asks again as the user keeps typing.
+const MAX_NOTE_COMPLETIONS: usize = 100;
+/// Bonus of a note modified just now, decreasing linearly to nothing after [`RECENCY_WINDOW`].
+const RECENCY_BONUS: f64 = 30.0;
+const RECENCY_WINDOW: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Bonus per folder shared with the note being edited.
+const FOLDER_BONUS: i64 = 10;
+
+/// The part of an unclosed wiki‑link typed before the cursor.
+pub struct LinkContext<'a> {
+    /// Byte offset (into the line) just past the opening `[[`.
+    pub target_start: usize,
+    /// Text typed between `[[` and the cursor.
+    pub target: &'a str,
+}
+
+/// Finds the wiki‑link being typed: the last `[[` before the cursor that is not closed yet.
+/// Returns `None` once the user has moved on to the alias (`|`).
+pub fn link_context(prefix: &str) -> Option<LinkContext<'_>> {
+    let start = prefix.rfind("[[")?;
+    let target = &prefix[start + 2..];
+    if target.contains("]]") || target.contains('|') {
+        return None;
+    }
+    Some(LinkContext {
+        target_start: start + 2,
+        target,
+    })
+}
+
+/// How the wiki-link being completed continues after the partial target.
+pub enum LinkEnd {
+    /// Nothing closes the link yet; `]]` is added.
+    Open,
+    /// The link is closed right after the target.
+    Closed,
+    /// An anchor or alias follows the target, so only the target is replaced.
+    Anchored,
+}
+
+/// Completion items for the notes matching the target typed after `[[`, best matches first.
+/// Notes are scored by fuzzy matching `query` against their title and virtual path, with a bonus
+/// for recently modified notes and for notes in the folder of `current` (the virtual path of the
+/// note being edited). Each alias of a note is offered as an item of its own, linking to the
+/// note with the alias as link text. Each item replaces `range`, the partially typed target.
+pub fn note_completions(
+    notes: &[IndexedNote],
+    query: &str,
+    current: Option<&str>,
+    range: Range,
+    end: LinkEnd,
+) -> CompletionList {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let query = query.trim();
+    let candidates = notes.iter().flat_map(|note| {
+        std::iter::once((note, None)).chain(
+            note.summary
+                .aliases
+                .iter()
+                .map(move |alias| (note, Some(alias.as_str()))),
+        )
+    });
+    let mut matches: Vec<(i64, &IndexedNote, Option<&str>)> = candidates
+        .filter_map(|(note, alias)| {
+            if query.is_empty() {
+                return Some((0, note, alias));
+            }
+            let score = match alias {
+                Some(alias) => matcher.fuzzy_match(alias, query),
+                None => matcher
+                    .fuzzy_match(&note.info.title, query)
+                    .max(matcher.fuzzy_match(&note.info.virtual_path, query)),
+            };
+            Some((score?, note, alias))
+        })
+        .collect();
+
+    let now = SystemTime::now();
+    for (score, note, _) in matches.iter_mut() {
+        if let Some(modified) = note.modified {
+            let age = now.duration_since(modified).unwrap_or_default();
+            let left = RECENCY_WINDOW.saturating_sub(age).as_secs_f64();
+            *score += (RECENCY_BONUS * left / RECENCY_WINDOW.as_secs_f64()) as i64;
+        }
+        if let Some(current) = current {
+            *score += FOLDER_BONUS * shared_folders(current, &note.info.virtual_path) as i64;
+        }
+    }
+    let name =
+        |note: &IndexedNote, alias: Option<&str>| alias.unwrap_or(&note.info.title).to_string();
+    matches.sort_by(|(a, a_note, a_alias), (b, b_note, b_alias)| {
+        b.cmp(a)
+            .then_with(|| name(a_note, *a_alias).cmp(&name(b_note, *b_alias)))
+    });
+
+    let is_incomplete = matches.len() > MAX_NOTE_COMPLETIONS;
+    let items = matches
+        .into_iter()
+        .take(MAX_NOTE_COMPLETIONS)
+        .enumerate()
+        .map(|(rank, (_, note, alias))| {
+            let info = &note.info;
+            let text = name(note, alias);
+            let with_text = !text.is_empty() && text != info.virtual_path;
+            let new_text = match end {
+                LinkEnd::Anchored => info.virtual_path.clone(),
+                LinkEnd::Closed if with_text => format!("{}|{}", info.virtual_path, text),
+                LinkEnd::Closed => info.virtual_path.clone(),
+                LinkEnd::Open if with_text => format!("{}|{}]]", info.virtual_path, text),
+                LinkEnd::Open => format!("{}]]", info.virtual_path),
+            };
+            CompletionItem {
+                label: format!("{} ({})", text, info.virtual_path),
+                kind: Some(CompletionItemKind::FILE),
+                detail: Some(match alias {
+                    Some(_) => format!("{} (alias of {})", info.virtual_path, info.title),
+                    None => info.virtual_path.clone(),
+                }),
+                // The client filters the items again as the user keeps typing, against the
+                // names the note was matched on.
+                filter_text: Some(match alias {
+                    Some(alias) => alias.to_string(),
+                    None if with_text => format!("{} {}", info.title, info.virtual_path),
+                    None => info.virtual_path.clone(),
+                }),
+                sort_text: Some(format!("{:05}", rank)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                // Read back by `resolve_note_completion`.
+                data: Some(json!({ "virtualPath": info.virtual_path })),
+                ..Default::default()
+            }
+        })
+        .collect();
+    CompletionList {
+        is_incomplete,
+        items,
+    }
+}
+
+/// Fills in the documentation of a note completion item once the client selects it: the backlink
+/// count, a summary of the frontmatter and a preview of the note, limited as hover previews are.
+pub async fn resolve_note_completion(
+    mut item: CompletionItem,
+    db: &db::Database,
+    ref_index: &HybridIndex,
+    open_notes: &HashMap<Url, Arc<ParsedNote>>,
+    encoding: PositionEncoding,
+    config: &HoverConfig,
+) -> CompletionItem {
+    let Some(virtual_path) = item
+        .data
+        .as_ref()
+        .and_then(|data| data.get("virtualPath"))
+        .and_then(|path| path.as_str())
+        .map(str::to_string)
+    else {
+        return item;
+    };
+    let Some((_, content)) = read_target(db, &virtual_path).await else {
+        return item;
+    };
+
+    let mut value = match ref_index
+        .get_references_count(&virtual_path, open_notes, encoding)
+        .await
+    {
+        Ok(1) => "1 backlink\n\n".to_string(),
+        Ok(count) => format!("{} backlinks\n\n", count),
+        Err(e) => {
+            error!("Could not count references to {}: {}", virtual_path, e);
+            String::new()
+        }
+    };
+    let note = ParsedNote::parse(&content, PositionEncoding::Utf8);
+    if let Some(frontmatter) = &note.frontmatter {
+        let summary = frontmatter_summary(frontmatter.value.value.as_ref());
+        if !summary.is_empty() {
+            value.push_str(&summary);
+            value.push_str("\n\n---\n\n");
+        }
+    }
+    value.push_str(&hover_preview::preview_markdown(&note, None, config));
+    item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    }));
+    item
+}
+
+/// Lists the top-level frontmatter keys with their value, one `- **key**: value` line each.
+/// Nested mappings are left out.
+fn frontmatter_summary(value: Option<&serde_yaml::Value>) -> String {
+    let Some(serde_yaml::Value::Mapping(mapping)) = value else {
+        return String::new();
+    };
+    let scalar = |value: &serde_yaml::Value| match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    };
+    let mut lines = Vec::new();
+    for (key, value) in mapping {
+        let Some(key) = scalar(key) else {
+            continue;
+        };
+        let value = match value {
+            serde_yaml::Value::Sequence(items) => items
+                .iter()
+                .filter_map(scalar)
+                .collect::<Vec<_>>()
+                .join(", "),
+            other => match scalar(other) {
+                Some(value) => value,
+                None => continue,
+            },
+        };
+        lines.push(format!("- **{}**: {}", key, value));
+    }
+    lines.join("\n")
+}
+
+/// Number of leading folders two virtual paths have in common.
+fn shared_folders(a: &str, b: &str) -> usize {
+    fn folder(path: &str) -> &str {
+        path.rsplit_once('/').map_or("", |(dir, _)| dir)
+    }
+    folder(a)
+        .split('/')
+        .zip(folder(b).split('/'))
+        .take_while(|(a, b)| !a.is_empty() && a == b)
+        .count()
+}
+
+/// Completion items for the anchors of the note at `virtual_path`, offered after
+/// `[[virtual_path#`: its headings, or its block ids when the anchor typed so far starts with
+/// `^`. Each item replaces `range` (the partially typed anchor).
+pub async fn anchor_completions(
+    db: &db::Database,
+    virtual_path: &str,
+    partial_anchor: &str,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let Some((file, content)) = read_target(db, virtual_path).await else {
+        return Vec::new();
+    };
+    let note = ParsedNote::parse(&content, PositionEncoding::Utf8);
+    if partial_anchor.starts_with('^') {
+        block_completions(&note, range)
+    } else {
+        heading_completions(&file, &note, range)
+    }
+}
+
+/// Reads the note a link points at.
+async fn read_target(db: &db::Database, virtual_path: &str) -> Option<(db::FileInfo, String)> {
+    let file = hover_preview::find_file(db, virtual_path).await?;
+    match fs::read_to_string(&file.path).await {
+        Ok(content) => Some((file, content)),
+        Err(e) => {
+            error!("Could not read {}: {}", file.path, e);
+            None
+        }
+    }
+}
+
+fn heading_completions(
+    file: &db::FileInfo,
+    note: &ParsedNote,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let title = note.title().unwrap_or_else(|| file.title.clone());
+    note.headings
+        .iter()
+        .map(|h| &h.value)
+        .filter(|h| !h.text.is_empty())
+        .enumerate()
+        .map(|(i, heading)| CompletionItem {
+            label: heading.text.clone(),
+            kind: Some(CompletionItemKind::REFERENCE),
+            detail: Some(format!("{} {}", "#".repeat(heading.level), title)),
+            // Keep the document order rather than the client's alphabetical one.
+            sort_text: Some(format!("{:05}", i)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: heading.text.clone(),
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn block_completions(note: &ParsedNote, range: Range) -> Vec<CompletionItem> {
+    note.block_ids
+        .iter()
+        .map(|b| &b.value)
+        .map(|block| {
+            let preview = blocks::block_lines(note, block).join("\n");
+            CompletionItem {
+                label: format!("^{}", block.id),
+                kind: Some(CompletionItemKind::REFERENCE),
+                detail: preview.lines().next().map(|l| l.trim().to_string()),
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: preview,
+                })),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: format!("^{}", block.id),
+                })),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,210 @@
+// src/config.rs
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+/// Workspace settings, read from the client's `initializationOptions` and refreshed on
+/// `workspace/didChangeConfiguration`. Every field has a default so clients only need to send
+/// the settings they want to change.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    pub diagnostics: DiagnosticsConfig,
+    pub formatting: FormattingConfig,
+    pub hover: HoverConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiagnosticsConfig {
+    /// Severity used for wiki-links whose target is not in the database.
+    pub broken_link_severity: SeveritySetting,
+    /// Severity used for `[[note#Heading]]` and `[[note#^id]]` links whose heading or block does
+    /// not exist in the target.
+    pub broken_anchor_severity: SeveritySetting,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            broken_link_severity: SeveritySetting::Warning,
+            broken_anchor_severity: SeveritySetting::Warning,
+        }
+    }
+}
+
+/// Style applied by document and range formatting.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormattingConfig {
+    /// Bullet of unordered list items: `-`, `*` or `+`. Any other character keeps the bullets
+    /// as written.
+    pub list_marker: char,
+    /// Delimiter of emphasis: `*` or `_`; strong emphasis uses it twice. Any other character
+    /// keeps the delimiters as written.
+    pub emphasis_marker: char,
+    /// Number of blank lines kept before and after top-level headings.
+    pub heading_blank_lines: usize,
+    pub trim_trailing_whitespace: bool,
+    /// Pads table cells so that the columns line up.
+    pub align_tables: bool,
+}
+
+impl Default for FormattingConfig {
+    fn default() -> Self {
+        Self {
+            list_marker: '-',
+            emphasis_marker: '*',
+            heading_blank_lines: 1,
+            trim_trailing_whitespace: true,
+            align_tables: true,
+        }
+    }
+}
+
+/// Content of the note previews shown when hovering a wiki-link.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HoverConfig {
+    /// Maximum number of lines of the note shown.
+    pub max_lines: usize,
+    /// Maximum number of characters of the note shown.
+    pub max_chars: usize,
+    pub show_title: bool,
+    pub show_tags: bool,
+    /// Shows how long ago the note was last modified.
+    pub show_modified: bool,
+}
+
+impl Default for HoverConfig {
+    fn default() -> Self {
+        Self {
+            max_lines: 20,
+            max_chars: 2000,
+            show_title: true,
+            show_tags: true,
+            show_modified: false,
+        }
+    }
+}
+
+/// A diagnostic severity as written in the settings, with `off` disabling the diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeveritySetting {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    Off,
+}
+
+impl SeveritySetting {
+    /// Returns the LSP severity, or `None` when the diagnostic is turned off.
+    pub fn to_lsp(self) -> Option<DiagnosticSeverity> {
+        match self {
+            SeveritySetting::Error => Some(DiagnosticSeverity::ERROR),
+            SeveritySetting::Warning => Some(DiagnosticSeverity::WARNING),
+            SeveritySetting::Information => Some(DiagnosticSeverity::INFORMATION),
+            SeveritySetting::Hint => Some(DiagnosticSeverity::HINT),
+            SeveritySetting::Off => None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses the settings sent by the client. Settings may either be sent as-is or nested under
+    /// a `gnosis` section; anything that fails to parse falls back to the defaults.
+    pub fn from_value(value: Option<Value>) -> Self {
+        let Some(mut value) = value else {
+            return Self::default();
+        };
+        if let Some(section) = value.get_mut("gnosis") {
+            value = section.take();
+        }
+        match serde_json::from_value(value) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!("Invalid settings, using defaults: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Applies the settings of a `workspace/didChangeConfiguration` notification on top of the
+    /// current ones. Settings missing from `value` keep their current value, so clients sending
+    /// `null` or `{}` leave the configuration untouched.
+    pub fn updated(&self, mut value: Value) -> Self {
+        if let Some(section) = value.get_mut("gnosis") {
+            value = section.take();
+        }
+        let mut merged = serde_json::to_value(self).unwrap_or(Value::Null);
+        merge(&mut merged, value);
+        match serde_json::from_value(merged) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!("Invalid settings, keeping the current ones: {}", e);
+                self.clone()
+            }
+        }
+    }
+}
+
+/// Recursively overwrites the fields of `base` with those of `update`. `null` values are
+/// ignored.
+fn merge(base: &mut Value, update: Value) {
+    match (base, update) {
+        (Value::Object(base), Value::Object(update)) => {
+            for (key, value) in update {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (_, Value::Null) => {}
+        (base, update) => *base = update,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn initial() -> Config {
+        Config::from_value(Some(json!({
+            "gnosis": {
+                "diagnostics": { "brokenLinkSeverity": "error" },
+                "hover": { "maxLines": 5 }
+            }
+        })))
+    }
+
+    #[test]
+    fn empty_settings_keep_the_current_config() {
+        for settings in [json!(null), json!({}), json!({ "gnosis": null })] {
+            let config = initial().updated(settings);
+            assert_eq!(
+                config.diagnostics.broken_link_severity,
+                SeveritySetting::Error
+            );
+            assert_eq!(config.hover.max_lines, 5);
+        }
+    }
+
+    #[test]
+    fn partial_settings_are_merged() {
+        let config = initial().updated(json!({ "hover": { "showModified": true } }));
+        assert_eq!(config.hover.max_lines, 5);
+        assert!(config.hover.show_modified);
+        assert_eq!(
+            config.diagnostics.broken_link_severity,
+            SeveritySetting::Error
+        );
+    }
+
+    #[test]
+    fn invalid_settings_keep_the_current_config() {
+        let config = initial().updated(json!({ "hover": { "maxLines": "many" } }));
+        assert_eq!(config.hover.max_lines, 5);
+    }
+}
@@ -76,6 +76,12 @@ impl Database {
         }
     }
 
+    /// Returns true when a connection pool is available. Callers use this to tell an empty
+    /// `files` table apart from a missing database.
+    pub fn is_available(&self) -> bool {
+        self.pool.is_some()
+    }
+
     /// Retrieves all file infos from the "files" table.
     /// The query assumes that the "files" table contains the columns:
     /// `virtual_path` (the wiki-link path) and `title` (the file title).
@@ -104,9 +110,90 @@ impl Database {
         }
     }
 
+    /// Registers a newly created note.
+    /// If the database is not available, this is a no-op.
+    pub async fn insert_file(&self, virtual_path: &str, title: &str, path: &str) -> Result<()> {
+        if let Some(ref pool) = self.pool {
+            sqlx::query("INSERT INTO files (virtual_path, title, path) VALUES (?, ?, ?)")
+                .bind(virtual_path)
+                .bind(title)
+                .bind(path)
+                .execute(pool)
+                .await?;
+        } else {
+            log::warn!("Database is not available. Not registering {}.", path);
+        }
+        Ok(())
+    }
+
+    /// Points the record of a moved note at its new local path and virtual path.
+    /// If the database is not available, this is a no-op.
+    pub async fn update_file_location(
+        &self,
+        old_path: &str,
+        new_path: &str,
+        new_virtual_path: &str,
+    ) -> Result<()> {
+        if let Some(ref pool) = self.pool {
+            sqlx::query("UPDATE files SET path = ?, virtual_path = ? WHERE path = ?")
+                .bind(new_path)
+                .bind(new_virtual_path)
+                .bind(old_path)
+                .execute(pool)
+                .await?;
+        } else {
+            log::warn!("Database is not available. Not updating {}.", old_path);
+        }
+        Ok(())
+    }
+
+    /// Gives a renamed note its new virtual path and local path, and its new title when given.
+    /// If the database is not available, this is a no-op.
+    pub async fn rename_file(
+        &self,
+        old_virtual_path: &str,
+        new_virtual_path: &str,
+        new_path: &str,
+        new_title: Option<&str>,
+    ) -> Result<()> {
+        if let Some(ref pool) = self.pool {
+            sqlx::query(
+                "UPDATE files SET virtual_path = ?, path = ?, title = COALESCE(?, title) \
+                 WHERE virtual_path = ?",
+            )
+            .bind(new_virtual_path)
+            .bind(new_path)
+            .bind(new_title)
+            .bind(old_virtual_path)
+            .execute(pool)
+            .await?;
+        } else {
+            log::warn!(
+                "Database is not available. Not renaming {}.",
+                old_virtual_path
+            );
+        }
+        Ok(())
+    }
+
+    /// Removes the record of a deleted note.
+    /// If the database is not available, this is a no-op.
+    pub async fn delete_file(&self, path: &str) -> Result<()> {
+        if let Some(ref pool) = self.pool {
+            sqlx::query("DELETE FROM files WHERE path = ?")
+                .bind(path)
+                .execute(pool)
+                .await?;
+        } else {
+            log::warn!("Database is not available. Not deleting {}.", path);
+        }
+        Ok(())
+    }
+
     /// (Test helper) Creates a Database instance from an existing SqlitePool.
     /// Only used in tests.
     #[cfg(test)]
+    #[allow(dead_code)]
     pub fn from_pool(pool: SqlitePool) -> Self {
         Self { pool: Some(pool) }
     }
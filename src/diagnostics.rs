@@ -0,0 +1,154 @@
+// src/diagnostics.rs
+
+use crate::config::DiagnosticsConfig;
+use crate::db;
+use crate::note::ParsedNote;
+use crate::position::PositionEncoding;
+use crate::wiki_links;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use tokio::fs;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+/// Diagnostic code attached to wiki-links whose target is missing from the database.
+pub const BROKEN_LINK_CODE: &str = "broken-wiki-link";
+/// Diagnostic code attached to `[[note#Heading]]` and `[[note#^id]]` links whose heading or block
+/// does not exist.
+pub const BROKEN_ANCHOR_CODE: &str = "broken-heading-anchor";
+
+/// Resolves every wiki-link of the note against the `files` table (or the `aliases` declared in
+/// frontmatter, mapped to virtual paths) and returns a diagnostic for
+/// each link whose virtual path is unknown, and for each heading or block anchor missing from its
+/// target.
+/// When the database is not available nothing can be resolved, so no diagnostics are produced.
+pub async fn get_diagnostics(
+    note: &ParsedNote,
+    db: &db::Database,
+    aliases: Arc<HashMap<String, String>>,
+    config: &DiagnosticsConfig,
+) -> Vec<Diagnostic> {
+    match LinkTargets::load(db, aliases).await {
+        Some(mut targets) => check_links(note, &mut targets, config).await,
+        None => Vec::new(),
+    }
+}
+
+/// The notes of the `files` table, loaded once to check the links of one or many notes.
+pub struct LinkTargets {
+    /// Local path of each virtual path.
+    paths: HashMap<String, String>,
+    /// Virtual path of the note declaring each alias.
+    aliases: Arc<HashMap<String, String>>,
+    /// Target notes are read and parsed at most once, however many anchored links point at
+    /// them.
+    notes: HashMap<String, Option<ParsedNote>>,
+}
+
+impl LinkTargets {
+    /// Returns `None` when the database is not available or cannot be queried.
+    pub async fn load(db: &db::Database, aliases: Arc<HashMap<String, String>>) -> Option<Self> {
+        if !db.is_available() {
+            return None;
+        }
+        match db.get_all_file_infos().await {
+            Ok(infos) => Some(Self {
+                paths: infos
+                    .into_iter()
+                    .map(|f| (f.virtual_path, f.path))
+                    .collect(),
+                aliases,
+                notes: HashMap::new(),
+            }),
+            Err(e) => {
+                log::error!("Error retrieving file infos from DB: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Local paths of every note in the database.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.paths.values().map(String::as_str)
+    }
+}
+
+/// Checks the wiki-links of a note against previously loaded targets.
+pub async fn check_links(
+    note: &ParsedNote,
+    targets: &mut LinkTargets,
+    config: &DiagnosticsConfig,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for spanned in &note.wiki_links {
+        let (link, range) = (&spanned.value, spanned.range);
+        let virtual_path = targets
+            .aliases
+            .get(&link.virtual_path)
+            .unwrap_or(&link.virtual_path);
+        let Some(path) = targets.paths.get(virtual_path) else {
+            if let Some(severity) = config.broken_link_severity.to_lsp() {
+                diagnostics.push(diagnostic(
+                    range,
+                    severity,
+                    BROKEN_LINK_CODE,
+                    format!("Wiki-link target not found: {}", link.virtual_path),
+                ));
+            }
+            continue;
+        };
+        let (Some(anchor), Some(severity)) = (&link.anchor, config.broken_anchor_severity.to_lsp())
+        else {
+            continue;
+        };
+        if !targets.notes.contains_key(path) {
+            let target = fs::read_to_string(path)
+                .await
+                .ok()
+                .map(|content| ParsedNote::parse(&content, PositionEncoding::Utf8));
+            targets.notes.insert(path.clone(), target);
+        }
+        if let Some(target) = &targets.notes[path] {
+            if wiki_links::resolve_anchor(target, anchor).is_none() {
+                let what = if anchor.starts_with('^') {
+                    "Block"
+                } else {
+                    "Heading"
+                };
+                diagnostics.push(diagnostic(
+                    range,
+                    severity,
+                    BROKEN_ANCHOR_CODE,
+                    format!("{} not found in {}: {}", what, link.virtual_path, anchor),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Result id of a diagnostic report, derived from its content so that a client asking again
+/// for a note whose problems did not change gets an `Unchanged` report.
+pub fn result_id(diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn diagnostic(
+    range: tower_lsp::lsp_types::Range,
+    severity: DiagnosticSeverity,
+    code: &str,
+    message: String,
+) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(code.to_string())),
+        source: Some("gnosis".to_string()),
+        message,
+        ..Default::default()
+    }
+}
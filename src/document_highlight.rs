@@ -0,0 +1,54 @@
+// src/document_highlight.rs
+
+use crate::note::ParsedNote;
+use std::ops;
+use tower_lsp::lsp_types::{DocumentHighlight, DocumentHighlightKind, Position};
+
+/// Highlights the elements of the note related to the one under the cursor: every wiki-link to
+/// the same virtual path (whatever its alias or anchor), every occurrence of the same tag, or
+/// the references and definition of the same footnote.
+pub fn get_highlights(note: &ParsedNote, position: Position) -> Vec<DocumentHighlight> {
+    let offset = note.offset(position);
+    let contains = |span: &ops::Range<usize>| span.start <= offset && offset <= span.end;
+
+    if let Some(link) = note.wiki_links.iter().find(|l| contains(&l.span)) {
+        return note
+            .wiki_links
+            .iter()
+            .filter(|l| l.value.virtual_path == link.value.virtual_path)
+            .map(|l| highlight(l.range, DocumentHighlightKind::TEXT))
+            .collect();
+    }
+    if let Some(tag) = note.tags.iter().find(|t| contains(&t.span)) {
+        let name = tag.value.name.to_lowercase();
+        return note
+            .tags
+            .iter()
+            .filter(|t| t.value.name.to_lowercase() == name)
+            .map(|t| highlight(t.range, DocumentHighlightKind::TEXT))
+            .collect();
+    }
+    if let Some(footnote) = note.footnotes.iter().find(|f| contains(&f.span)) {
+        return note
+            .footnotes
+            .iter()
+            .filter(|f| f.value.label == footnote.value.label)
+            .map(|f| {
+                let kind = if f.value.definition {
+                    DocumentHighlightKind::WRITE
+                } else {
+                    DocumentHighlightKind::READ
+                };
+                highlight(f.range, kind)
+            })
+            .collect();
+    }
+    Vec::new()
+}
+
+fn highlight(range: tower_lsp::lsp_types::Range, kind: DocumentHighlightKind) -> DocumentHighlight {
+    DocumentHighlight {
+        range,
+        kind: Some(kind),
+    }
+}
@@ -0,0 +1,214 @@
+// src/document_links.rs
+
+use crate::db;
+use crate::document_store::DocumentStore;
+use crate::note::ParsedNote;
+use crate::note_index::NoteIndex;
+use crate::position::PositionEncoding;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::DocumentLink;
+use url::Url;
+
+/// Documents with more links than this get their links resolved lazily through
+/// `documentLink/resolve` instead of querying every target upfront.
+const EAGER_RESOLVE_LIMIT: usize = 200;
+
+/// What a lazily resolved link points at, stored in `DocumentLink::data`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum LinkData {
+    Wiki { virtual_path: String },
+    Markdown { path: String },
+}
+
+/// Returns a link for every wiki‑link and every relative `[text](note.md)` link in the document.
+/// Small documents are resolved right away (target and note title tooltip); larger ones carry
+/// `data` and are completed by [`resolve_document_link`]. `[[Alias]]` links resolve to the note
+/// declaring the alias.
+pub async fn get_document_links(
+    uri: &Url,
+    note: &ParsedNote,
+    db: &db::Database,
+    index: &NoteIndex,
+    documents: &DocumentStore,
+) -> Vec<DocumentLink> {
+    let base_dir = uri
+        .to_file_path()
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf));
+
+    let mut links = Vec::new();
+    for link in &note.wiki_links {
+        links.push(DocumentLink {
+            range: link.range,
+            target: None,
+            tooltip: None,
+            data: serde_json::to_value(LinkData::Wiki {
+                virtual_path: link.value.virtual_path.clone(),
+            })
+            .ok(),
+        });
+    }
+    if let Some(base_dir) = &base_dir {
+        for link in &note.markdown_links {
+            let Some(path) = resolve_relative_path(base_dir, &link.value.destination) else {
+                continue;
+            };
+            links.push(DocumentLink {
+                range: link.range,
+                target: Url::from_file_path(&path).ok(),
+                tooltip: None,
+                data: serde_json::to_value(LinkData::Markdown {
+                    path: path.to_string_lossy().to_string(),
+                })
+                .ok(),
+            });
+        }
+    }
+
+    if links.len() > EAGER_RESOLVE_LIMIT {
+        return links;
+    }
+    let infos = match db.get_all_file_infos().await {
+        Ok(infos) => infos,
+        Err(e) => {
+            log::error!("Error retrieving file infos from DB: {}", e);
+            return links;
+        }
+    };
+    let aliases = index.aliases(db, documents, note.encoding).await;
+    let targets = Targets::new(&infos, &aliases);
+    let mut resolved = Vec::new();
+    for link in links {
+        if let Some(link) = targets.resolve(link, index, note.encoding).await {
+            resolved.push(link);
+        }
+    }
+    resolved
+}
+
+/// Completes a link returned without target by [`get_document_links`].
+pub async fn resolve_document_link(
+    link: DocumentLink,
+    db: &db::Database,
+    index: &NoteIndex,
+    documents: &DocumentStore,
+    encoding: PositionEncoding,
+) -> DocumentLink {
+    let infos = match db.get_all_file_infos().await {
+        Ok(infos) => infos,
+        Err(e) => {
+            log::error!("Error retrieving file infos from DB: {}", e);
+            return link;
+        }
+    };
+    let aliases = index.aliases(db, documents, encoding).await;
+    let fallback = link.clone();
+    Targets::new(&infos, &aliases)
+        .resolve(link, index, encoding)
+        .await
+        .unwrap_or(fallback)
+}
+
+/// The notes of the `files` table, indexed for link resolution.
+struct Targets<'a> {
+    by_virtual_path: HashMap<&'a str, &'a db::FileInfo>,
+    by_path: HashMap<&'a str, &'a db::FileInfo>,
+    /// Virtual path of the note declaring each alias, see [`NoteIndex::aliases`].
+    aliases: &'a HashMap<String, String>,
+}
+
+impl<'a> Targets<'a> {
+    fn new(infos: &'a [db::FileInfo], aliases: &'a HashMap<String, String>) -> Self {
+        Self {
+            by_virtual_path: infos.iter().map(|f| (f.virtual_path.as_str(), f)).collect(),
+            by_path: infos.iter().map(|f| (f.path.as_str(), f)).collect(),
+            aliases,
+        }
+    }
+
+    /// Fills in target and tooltip, the title of the target note. Returns `None` for wiki‑links
+    /// whose target is not in the database, since there is nothing to open.
+    async fn resolve(
+        &self,
+        mut link: DocumentLink,
+        index: &NoteIndex,
+        encoding: PositionEncoding,
+    ) -> Option<DocumentLink> {
+        let data: LinkData = match link.data.take() {
+            Some(data) => serde_json::from_value(data).ok()?,
+            None => return Some(link),
+        };
+        match data {
+            LinkData::Wiki { virtual_path } => {
+                let virtual_path = self.aliases.get(&virtual_path).unwrap_or(&virtual_path);
+                let info = self.by_virtual_path.get(virtual_path.as_str())?;
+                link.target = Url::from_file_path(&info.path).ok();
+                link.tooltip = Some(index.title(info, encoding).await);
+            }
+            LinkData::Markdown { path } => {
+                if let Some(info) = self.by_path.get(path.as_str()) {
+                    link.tooltip = Some(index.title(info, encoding).await);
+                }
+            }
+        }
+        Some(link)
+    }
+}
+
+/// Resolves the destination of a markdown link against the folder of the current document.
+/// External URLs and same-document `#fragment` links are left to the editor.
+fn resolve_relative_path(base_dir: &Path, destination: &str) -> Option<PathBuf> {
+    if destination.is_empty()
+        || destination.starts_with('#')
+        || destination.contains("://")
+        || destination.starts_with("mailto:")
+    {
+        return None;
+    }
+    let without_fragment = destination.split('#').next()?;
+    let decoded = percent_decode(without_fragment);
+    let path = Path::new(&decoded);
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base_dir.join(path)
+    };
+    Some(normalize(&joined))
+}
+
+/// Removes `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+/// Decodes `%XX` escapes, as used for spaces in markdown link destinations.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(Ok(value)) = s.get(i + 1..i + 3).map(|h| u8::from_str_radix(h, 16)) {
+                out.push(value);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
@@ -0,0 +1,241 @@
+// src/document_store.rs
+
+use crate::note::ParsedNote;
+use crate::position::PositionEncoding;
+use ropey::Rope;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tower_lsp::lsp_types::{Position, TextDocumentContentChangeEvent};
+use url::Url;
+
+/// An open document. The text lives in a rope, so ranged edits and line lookups are cheap and
+/// cloning a document only bumps reference counts.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub rope: Rope,
+    /// The version sent by the client with the last change.
+    pub version: i32,
+}
+
+impl Document {
+    pub fn new(text: &str, version: i32) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            version,
+        }
+    }
+
+    /// Returns the full text of the document.
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    /// Returns the line at `line_index` without its line ending.
+    pub fn line(&self, line_index: usize) -> Option<String> {
+        if line_index >= self.rope.len_lines() {
+            return None;
+        }
+        let mut line = self.rope.line(line_index).to_string();
+        while line.ends_with('\n') || line.ends_with('\r') {
+            line.pop();
+        }
+        Some(line)
+    }
+
+    /// Applies the content changes of a `textDocument/didChange` notification, in order.
+    /// A change without a range replaces the whole document.
+    pub fn apply_changes(
+        &mut self,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+        encoding: PositionEncoding,
+    ) {
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = self.position_to_char(range.start, encoding);
+                    let end = self.position_to_char(range.end, encoding).max(start);
+                    self.rope.remove(start..end);
+                    self.rope.insert(start, &change.text);
+                }
+                None => self.rope = Rope::from_str(&change.text),
+            }
+        }
+        self.version = version;
+    }
+
+    /// Converts an LSP position to a char index into the rope.
+    /// Positions past the end of a line are clamped to the end of that line.
+    fn position_to_char(&self, pos: Position, encoding: PositionEncoding) -> usize {
+        let line_index = pos.line as usize;
+        if line_index >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+        let line_start = self.rope.line_to_char(line_index);
+        let mut column = 0;
+        for (i, c) in self.rope.line(line_index).chars().enumerate() {
+            if column >= pos.character as usize || c == '\n' || c == '\r' {
+                return line_start + i;
+            }
+            column += encoding.char_len(c);
+        }
+        line_start + self.rope.line(line_index).len_chars()
+    }
+}
+
+/// The documents currently open in the editor, keyed by URI.
+#[derive(Default)]
+pub struct DocumentStore {
+    docs: RwLock<HashMap<Url, Document>>,
+    /// Parsed form of each document, with the version it was parsed from.
+    notes: RwLock<HashMap<Url, (i32, Arc<ParsedNote>)>>,
+}
+
+impl DocumentStore {
+    pub async fn open(&self, uri: Url, text: &str, version: i32) {
+        self.notes.write().await.remove(&uri);
+        let mut docs = self.docs.write().await;
+        docs.insert(uri, Document::new(text, version));
+    }
+
+    /// Applies incremental changes to an open document and returns the updated document.
+    pub async fn change(
+        &self,
+        uri: &Url,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        version: i32,
+        encoding: PositionEncoding,
+    ) -> Option<Document> {
+        let mut docs = self.docs.write().await;
+        let doc = docs.get_mut(uri)?;
+        if version <= doc.version {
+            log::warn!(
+                "Out-of-order change for {} (version {} after {})",
+                uri,
+                version,
+                doc.version
+            );
+        }
+        doc.apply_changes(changes, version, encoding);
+        Some(doc.clone())
+    }
+
+    pub async fn close(&self, uri: &Url) {
+        self.docs.write().await.remove(uri);
+        self.notes.write().await.remove(uri);
+    }
+
+    /// Returns the parsed form of an open document. A document is parsed at most once per
+    /// version, however many requests need it.
+    pub async fn note(&self, uri: &Url, encoding: PositionEncoding) -> Option<Arc<ParsedNote>> {
+        let doc = self.get(uri).await?;
+        if let Some((version, note)) = self.notes.read().await.get(uri) {
+            if *version == doc.version {
+                return Some(note.clone());
+            }
+        }
+        let note = Arc::new(ParsedNote::parse(&doc.text(), encoding));
+        self.notes
+            .write()
+            .await
+            .insert(uri.clone(), (doc.version, note.clone()));
+        Some(note)
+    }
+
+    /// Returns the parsed form of every open document, through the same per-version cache as
+    /// [`DocumentStore::note`].
+    pub async fn notes(&self, encoding: PositionEncoding) -> HashMap<Url, Arc<ParsedNote>> {
+        let uris: Vec<Url> = self.docs.read().await.keys().cloned().collect();
+        let mut notes = HashMap::with_capacity(uris.len());
+        for uri in uris {
+            if let Some(note) = self.note(&uri, encoding).await {
+                notes.insert(uri, note);
+            }
+        }
+        notes
+    }
+
+    /// Returns a snapshot of an open document.
+    pub async fn get(&self, uri: &Url) -> Option<Document> {
+        self.docs.read().await.get(uri).cloned()
+    }
+
+    /// Returns a snapshot of every open document.
+    pub async fn all(&self) -> Vec<(Url, Document)> {
+        let docs = self.docs.read().await;
+        docs.iter().map(|(u, d)| (u.clone(), d.clone())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn only_lsp_line_breaks_split_lines() {
+        let doc = Document::new("a\u{2028}b\u{85}c\nd\re\r\nf\u{c}g", 0);
+        assert_eq!(doc.rope.len_lines(), 4);
+        assert_eq!(doc.line(0).as_deref(), Some("a\u{2028}b\u{85}c"));
+        assert_eq!(doc.line(1).as_deref(), Some("d"));
+        assert_eq!(doc.line(2).as_deref(), Some("e"));
+        assert_eq!(doc.line(3).as_deref(), Some("f\u{c}g"));
+        assert_eq!(doc.line(4), None);
+    }
+
+    #[test]
+    fn applies_edits_after_unicode_separators() {
+        let mut doc = Document::new("a\u{2028}b\nc\n", 0);
+        doc.apply_changes(vec![edit((1, 0), (1, 1), "x")], 1, PositionEncoding::Utf16);
+        assert_eq!(doc.text(), "a\u{2028}b\nx\n");
+        assert_eq!(doc.version, 1);
+    }
+
+    #[test]
+    fn applies_edits_across_crlf() {
+        let mut doc = Document::new("one\r\ntwo\r\nthree", 0);
+        doc.apply_changes(
+            vec![edit((0, 3), (1, 3), ""), edit((1, 0), (1, 5), "3")],
+            1,
+            PositionEncoding::Utf16,
+        );
+        assert_eq!(doc.text(), "one\r\n3");
+    }
+
+    #[test]
+    fn clamps_columns_to_the_line_ending() {
+        let mut doc = Document::new("ab\r\ncd", 0);
+        doc.apply_changes(
+            vec![edit((0, 10), (0, 10), "!")],
+            1,
+            PositionEncoding::Utf16,
+        );
+        assert_eq!(doc.text(), "ab!\r\ncd");
+    }
+
+    #[test]
+    fn counts_columns_in_the_negotiated_encoding() {
+        // `é` is 2 UTF-8 bytes, `😀` 4 UTF-8 bytes, 2 UTF-16 units and 1 UTF-32 unit.
+        for (encoding, column) in [
+            (PositionEncoding::Utf8, 6),
+            (PositionEncoding::Utf16, 3),
+            (PositionEncoding::Utf32, 2),
+        ] {
+            let mut doc = Document::new("é😀x\n", 0);
+            doc.apply_changes(vec![edit((0, column), (0, column + 1), "y")], 1, encoding);
+            assert_eq!(doc.text(), "é😀y\n", "{:?}", encoding);
+        }
+    }
+}
@@ -1,46 +1,72 @@
 // src/document_symbols.rs
 
-use tower_lsp::lsp_types::{DocumentSymbol, Position, Range, SymbolKind};
+use crate::headings::Heading;
+use crate::note::ParsedNote;
+use tower_lsp::lsp_types::{DocumentSymbol, Range, SymbolKind};
 
-/// Extracts document symbols (headings) from a markdown document.
-/// This simple implementation scans each line for a leading '#' character.
-pub fn extract_symbols(text: &str) -> Vec<DocumentSymbol> {
-    let mut symbols = Vec::new();
+/// Builds the outline of a note as a tree of document symbols: each heading contains the
+/// headings of lower level that follow it. Setext headings are included, and `#` lines inside
+/// code blocks or the frontmatter are not mistaken for headings.
+/// A symbol's range spans its whole section, up to the next heading of the same or a higher
+/// level; its selection range is the heading itself.
+pub fn extract_symbols(note: &ParsedNote) -> Vec<DocumentSymbol> {
+    // Symbols whose section is still open, from the outermost to the innermost.
+    let mut stack: Vec<(usize, DocumentSymbol)> = Vec::new();
+    let mut roots = Vec::new();
 
-    for (line_index, line) in text.lines().enumerate() {
-        let trimmed = line.trim_start();
-        if trimmed.starts_with('#') {
-            // Count the number of '#' to infer the heading level.
-            let _level = trimmed.chars().take_while(|&c| c == '#').count();
-            // Extract the heading text after the '#' characters.
-            let heading_text = trimmed.trim_start_matches('#').trim();
+    for (i, heading) in note.headings.iter().enumerate() {
+        let level = heading.value.level;
+        while stack.last().is_some_and(|(l, _)| *l >= level) {
+            let (_, symbol) = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, symbol);
+        }
+        let range = note.range(&note.section(i));
+        stack.push((level, new_symbol(&heading.value, range, heading.range)));
+    }
+    while let Some((_, symbol)) = stack.pop() {
+        attach(&mut stack, &mut roots, symbol);
+    }
+    roots
+}
+
+/// Adds a finished symbol to its parent, or to the roots when it has none.
+fn attach(
+    stack: &mut [(usize, DocumentSymbol)],
+    roots: &mut Vec<DocumentSymbol>,
+    symbol: DocumentSymbol,
+) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+        None => roots.push(symbol),
+    }
+}
 
-            // Create a range covering the whole line.
-            let start = Position {
-                line: line_index as u32,
-                character: 0,
-            };
-            let end = Position {
-                line: line_index as u32,
-                character: line.len() as u32,
-            };
-            let range = Range { start, end };
+#[allow(deprecated)] // `DocumentSymbol::deprecated` has to be set even though it is unused.
+fn new_symbol(heading: &Heading, range: Range, selection_range: Range) -> DocumentSymbol {
+    DocumentSymbol {
+        name: if heading.text.is_empty() {
+            "(untitled)".to_string()
+        } else {
+            heading.text.clone()
+        },
+        detail: Some(format!("H{}", heading.level)),
+        kind: heading_kind(heading.level),
+        range,
+        selection_range,
+        children: None,
+        tags: None,
+        deprecated: None,
+    }
+}
 
-            // Create the document symbol.
-            let symbol = DocumentSymbol {
-                name: heading_text.to_string(),
-                detail: None,
-                // Use a suitable SymbolKind. Here we use SymbolKind::String as an example.
-                kind: SymbolKind::STRING,
-                range,
-                selection_range: range,
-                children: None,
-                // Add tags as None (or a vector of tags if desired)
-                tags: None,
-                deprecated: None,
-            };
-            symbols.push(symbol);
-        }
+/// Distinct symbol kinds per heading level, so editors show different icons for each level.
+fn heading_kind(level: usize) -> SymbolKind {
+    match level {
+        1 => SymbolKind::MODULE,
+        2 => SymbolKind::NAMESPACE,
+        3 => SymbolKind::CLASS,
+        4 => SymbolKind::METHOD,
+        5 => SymbolKind::FUNCTION,
+        _ => SymbolKind::FIELD,
     }
-    symbols
 }
@@ -0,0 +1,56 @@
+// src/folding_ranges.rs
+
+use crate::note::ParsedNote;
+use std::ops;
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+/// Returns the folding ranges of a note: heading sections (nested by level), the frontmatter,
+/// code blocks, block quotes and callouts, list items spanning several lines, and HTML
+/// comments. Ranges covering a single line are left out since there is nothing to fold.
+pub fn get_folding_ranges(note: &ParsedNote) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut push = |span: ops::Range<usize>, kind: FoldingRangeKind| {
+        if let Some(range) = folding_range(note, span, kind) {
+            ranges.push(range);
+        }
+    };
+
+    if let Some(frontmatter) = &note.frontmatter {
+        push(frontmatter.span.clone(), FoldingRangeKind::Region);
+    }
+    for i in 0..note.headings.len() {
+        push(note.section(i), FoldingRangeKind::Region);
+    }
+    let regions = note
+        .code_blocks
+        .iter()
+        .chain(&note.block_quotes)
+        .chain(&note.list_items);
+    for span in regions {
+        push(span.clone(), FoldingRangeKind::Region);
+    }
+    for span in &note.html_comments {
+        push(span.clone(), FoldingRangeKind::Comment);
+    }
+
+    ranges.sort_by_key(|r| (r.start_line, std::cmp::Reverse(r.end_line)));
+    ranges
+}
+
+/// Folds the lines of `span`, ignoring the line breaks and blank lines it ends with.
+fn folding_range(
+    note: &ParsedNote,
+    span: ops::Range<usize>,
+    kind: FoldingRangeKind,
+) -> Option<FoldingRange> {
+    let end = span.start + note.text[span.clone()].trim_end().len();
+    let range = note.range(&(span.start..end));
+    (range.end.line > range.start.line).then_some(FoldingRange {
+        start_line: range.start.line,
+        start_character: None,
+        end_line: range.end.line,
+        end_character: None,
+        kind: Some(kind),
+        collapsed_text: None,
+    })
+}
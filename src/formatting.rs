@@ -0,0 +1,428 @@
+// src/formatting.rs
+
+use crate::config::FormattingConfig;
+use crate::note::ParsedNote;
+use std::collections::BTreeMap;
+use std::ops;
+use tower_lsp::lsp_types::{Range, TextEdit};
+use unicode_width::UnicodeWidthStr;
+
+/// A line of the note being formatted.
+struct Line {
+    /// Byte range of the line content, line ending excluded.
+    content: ops::Range<usize>,
+    /// `\n`, `\r\n` or nothing for the last line.
+    eol: String,
+    /// Code blocks, frontmatter and HTML comments are kept exactly as written.
+    protected: bool,
+}
+
+/// Column alignment of a table, from its delimiter row.
+#[derive(Clone, Copy, PartialEq)]
+enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// Formats a note: list bullets, emphasis delimiters, blank lines around top-level headings,
+/// trailing whitespace and table columns, following `config`. Fenced code, the frontmatter and
+/// HTML comments are never touched.
+///
+/// With a `range`, only the edits touching its lines are returned; an edit may extend past the
+/// range to cover a whole table or blank-line run.
+pub fn format_note(
+    note: &ParsedNote,
+    config: &FormattingConfig,
+    range: Option<Range>,
+) -> Vec<TextEdit> {
+    let text = note.text.as_str();
+    let lines = split_lines(note);
+    let doc_eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let delimiters = delimiter_replacements(note, config);
+    let mut contents: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let mut content = text[line.content.clone()].to_string();
+            if !line.protected {
+                for (&offset, &c) in delimiters.range(line.content.clone()) {
+                    let at = offset - line.content.start;
+                    content.replace_range(at..at + 1, c.encode_utf8(&mut [0; 4]));
+                }
+            }
+            content
+        })
+        .collect();
+
+    if config.trim_trailing_whitespace {
+        for (i, line) in lines.iter().enumerate() {
+            if !line.protected {
+                let keep_break = is_hard_break(note, &lines, i);
+                let content = &mut contents[i];
+                content.truncate(content.trim_end().len());
+                if keep_break {
+                    content.push_str("  ");
+                }
+            }
+        }
+    }
+
+    if config.align_tables {
+        for table in &note.tables {
+            align_table(note, &lines, table, &mut contents);
+        }
+    }
+
+    // Each line is replaced by its new content, possibly preceded by inserted blank lines, or
+    // removed altogether when it belongs to a blank-line run that is too long.
+    let mut output: Vec<String> = lines
+        .iter()
+        .zip(&contents)
+        .map(|(line, content)| format!("{}{}", content, line.eol))
+        .collect();
+    for (line_idx, count) in heading_blank_lines(note, &lines, config.heading_blank_lines) {
+        let mut run_start = line_idx;
+        while run_start > 0 && is_blank(text, &lines[run_start - 1]) {
+            run_start -= 1;
+        }
+        let run = line_idx - run_start;
+        for (n, i) in (run_start..line_idx).enumerate() {
+            output[i] = if n < count {
+                lines[i].eol.clone()
+            } else {
+                String::new()
+            };
+        }
+        if run < count {
+            output[line_idx].insert_str(0, &doc_eol.repeat(count - run));
+        }
+    }
+
+    let lines_in_range = range.map(|r| r.start.line as usize..=r.end.line as usize);
+    let mut edits = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if output[i] == line_text(text, &lines[i]) {
+            i += 1;
+            continue;
+        }
+        let first = i;
+        while i < lines.len() && output[i] != line_text(text, &lines[i]) {
+            i += 1;
+        }
+        if let Some(in_range) = &lines_in_range {
+            if first > *in_range.end() || i - 1 < *in_range.start() {
+                continue;
+            }
+        }
+        let span = lines[first].content.start..lines[i - 1].content.end + lines[i - 1].eol.len();
+        edits.push(TextEdit {
+            range: note.range(&span),
+            new_text: output[first..i].concat(),
+        });
+    }
+    edits
+}
+
+fn split_lines(note: &ParsedNote) -> Vec<Line> {
+    let protected: Vec<&ops::Range<usize>> = note
+        .code_blocks
+        .iter()
+        .chain(&note.html_comments)
+        .chain(note.frontmatter.iter().map(|f| &f.span))
+        .collect();
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for raw in note.text.split_inclusive('\n') {
+        let content_len = raw.trim_end_matches(['\n', '\r']).len();
+        let whole = start..start + raw.len();
+        lines.push(Line {
+            content: start..start + content_len,
+            eol: raw[content_len..].to_string(),
+            protected: protected
+                .iter()
+                .any(|p| p.start < whole.end && whole.start < p.end),
+        });
+        start += raw.len();
+    }
+    lines
+}
+
+fn line_text<'a>(text: &'a str, line: &Line) -> &'a str {
+    &text[line.content.start..line.content.end + line.eol.len()]
+}
+
+fn is_blank(text: &str, line: &Line) -> bool {
+    !line.protected && text[line.content.clone()].trim().is_empty()
+}
+
+fn line_at(lines: &[Line], offset: usize) -> usize {
+    lines
+        .partition_point(|l| l.content.end + l.eol.len() <= offset)
+        .min(lines.len().saturating_sub(1))
+}
+
+/// Single-byte replacements of list bullets and emphasis delimiters, keyed by byte offset.
+fn delimiter_replacements(note: &ParsedNote, config: &FormattingConfig) -> BTreeMap<usize, char> {
+    let text = note.text.as_bytes();
+    let mut replacements = BTreeMap::new();
+
+    if matches!(config.list_marker, '-' | '*' | '+') {
+        for item in &note.list_items {
+            if matches!(text.get(item.start), Some(b'-' | b'*' | b'+')) {
+                replacements.insert(item.start, config.list_marker);
+            }
+        }
+    }
+
+    let marker = config.emphasis_marker;
+    if matches!(marker, '*' | '_') {
+        let delimited = note
+            .emphasis
+            .iter()
+            .map(|span| (span, 1))
+            .chain(note.strong.iter().map(|span| (span, 2)));
+        for (span, width) in delimited {
+            if span.len() < 2 * width || !matches!(text[span.start], b'*' | b'_') {
+                continue;
+            }
+            // `_` does not delimit emphasis inside a word, so such spans keep their `*`.
+            let intraword = |offset: Option<usize>| {
+                offset
+                    .and_then(|o| note.text.get(o..)?.chars().next())
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_')
+            };
+            if marker == '_' && (intraword(span.start.checked_sub(1)) || intraword(Some(span.end)))
+            {
+                continue;
+            }
+            for offset in (span.start..span.start + width).chain(span.end - width..span.end) {
+                replacements.insert(offset, marker);
+            }
+        }
+    }
+    replacements
+}
+
+/// Whether the trailing spaces of a line are a hard line break: two or more spaces ending a
+/// line that is followed by more text of the same paragraph or list item.
+fn is_hard_break(note: &ParsedNote, lines: &[Line], index: usize) -> bool {
+    let line = &lines[index];
+    let content = &note.text[line.content.clone()];
+    let Some(next) = lines.get(index + 1) else {
+        return false;
+    };
+    if content.trim().is_empty() || !content.ends_with("  ") {
+        return false;
+    }
+    let starts_item = note
+        .list_items
+        .iter()
+        .any(|item| next.content.contains(&item.start));
+    note.paragraphs
+        .iter()
+        .chain(&note.list_items)
+        .any(|p| p.start <= line.content.end && next.content.start < p.end)
+        && !starts_item
+        && !note.text[next.content.clone()].trim().is_empty()
+}
+
+/// Rewrites the rows of a table with padded cells. Only tables starting at the beginning of a
+/// line are aligned; tables nested in lists or quotes are left alone.
+fn align_table(
+    note: &ParsedNote,
+    lines: &[Line],
+    table: &ops::Range<usize>,
+    contents: &mut [String],
+) {
+    let first = line_at(lines, table.start);
+    if lines[first].content.start != table.start {
+        return;
+    }
+    let last = line_at(lines, note.text[..table.end].trim_end().len());
+    let mut rows: Vec<Vec<String>> = (first..=last).map(|i| split_row(&contents[i])).collect();
+    let columns = rows[0].len();
+    if rows.len() < 2 || rows.iter().any(|r| r.len() > columns) {
+        return;
+    }
+    for row in &mut rows {
+        row.resize(columns, String::new());
+    }
+    let alignments: Vec<Alignment> = rows[1].iter().map(|c| alignment(c)).collect();
+    let widths: Vec<usize> = (0..columns)
+        .map(|col| {
+            rows.iter()
+                .enumerate()
+                .filter(|(i, _)| *i != 1)
+                .map(|(_, row)| row[col].width())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+
+    for (row_idx, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(col, cell)| {
+                let width = widths[col];
+                if row_idx == 1 {
+                    return delimiter_cell(alignments[col], width);
+                }
+                let pad = width - cell.width();
+                match alignments[col] {
+                    Alignment::Right => format!("{}{}", " ".repeat(pad), cell),
+                    Alignment::Center => format!(
+                        "{}{}{}",
+                        " ".repeat(pad / 2),
+                        cell,
+                        " ".repeat(pad - pad / 2)
+                    ),
+                    Alignment::None | Alignment::Left => format!("{}{}", cell, " ".repeat(pad)),
+                }
+            })
+            .collect();
+        contents[first + row_idx] = format!("| {} |", cells.join(" | "));
+    }
+}
+
+/// Splits a table row into trimmed cells. Pipes that are escaped or inside code spans do not
+/// separate cells.
+fn split_row(row: &str) -> Vec<String> {
+    let row = row.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = match row.strip_suffix('|') {
+        Some(stripped) if !stripped.ends_with('\\') => stripped,
+        _ => row,
+    };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut in_code = false;
+    let mut escaped = false;
+    for c in row.chars() {
+        match c {
+            '|' if !escaped && !in_code => cells.push(std::mem::take(&mut cell)),
+            _ => {
+                if c == '`' && !escaped {
+                    in_code = !in_code;
+                }
+                cell.push(c);
+            }
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.push(cell);
+    cells.into_iter().map(|c| c.trim().to_string()).collect()
+}
+
+fn alignment(cell: &str) -> Alignment {
+    match (cell.starts_with(':'), cell.ends_with(':') && cell.len() > 1) {
+        (true, true) => Alignment::Center,
+        (true, false) => Alignment::Left,
+        (false, true) => Alignment::Right,
+        (false, false) => Alignment::None,
+    }
+}
+
+fn delimiter_cell(alignment: Alignment, width: usize) -> String {
+    match alignment {
+        Alignment::None => "-".repeat(width),
+        Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+        Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+        Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+    }
+}
+
+/// The lines that must be preceded by `count` blank lines: top-level headings that follow other
+/// content, and the content that follows a top-level heading.
+fn heading_blank_lines(note: &ParsedNote, lines: &[Line], count: usize) -> BTreeMap<usize, usize> {
+    let text = note.text.as_str();
+    let mut required = BTreeMap::new();
+    let nested = |offset: usize| {
+        note.block_quotes
+            .iter()
+            .chain(&note.list_items)
+            .any(|b| b.start <= offset && offset < b.end)
+    };
+    for heading in &note.headings {
+        let first = line_at(lines, heading.span.start);
+        if lines[first].content.start != heading.span.start || nested(heading.span.start) {
+            continue;
+        }
+        let last = line_at(lines, heading.span.end);
+        if (0..first).rev().any(|i| !is_blank(text, &lines[i])) {
+            required.insert(first, count);
+        }
+        if let Some(next) = (last + 1..lines.len()).find(|&i| !is_blank(text, &lines[i])) {
+            required.insert(next, count);
+        }
+    }
+    required
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::PositionEncoding;
+
+    fn format(text: &str, config: &FormattingConfig) -> String {
+        let note = ParsedNote::parse(text, PositionEncoding::Utf16);
+        note.apply_edits(&format_note(&note, config, None))
+    }
+
+    #[test]
+    fn table_columns_are_aligned_by_display_width() {
+        let text = "| 名前 | Age |\n|---|:-:|\n| 山田太郎 | 3 |\n";
+        assert_eq!(
+            format(text, &FormattingConfig::default()),
+            "| 名前     | Age |\n| -------- | :-: |\n| 山田太郎 |  3  |\n"
+        );
+    }
+
+    #[test]
+    fn hard_breaks_keep_two_trailing_spaces() {
+        let text = "one   \ntwo  \n\nthree\t\n";
+        assert_eq!(
+            format(text, &FormattingConfig::default()),
+            "one  \ntwo\n\nthree\n"
+        );
+    }
+
+    #[test]
+    fn blank_line_runs_around_headings_are_normalized() {
+        let text = "# Title\n\n\n\nText\n# Next\nMore\n";
+        assert_eq!(
+            format(text, &FormattingConfig::default()),
+            "# Title\n\nText\n\n# Next\n\nMore\n"
+        );
+        let config = FormattingConfig {
+            heading_blank_lines: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            format("Intro\n# Title\nText\n", &config),
+            "Intro\n\n\n# Title\n\n\nText\n"
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings_are_kept() {
+        let text = "# A\r\nText  \r\n* item\r\n";
+        assert_eq!(
+            format(text, &FormattingConfig::default()),
+            "# A\r\n\r\nText\r\n- item\r\n"
+        );
+    }
+
+    #[test]
+    fn markers_are_replaced_outside_code() {
+        let text = "* a\n+ _b_ and __c__\n\n```\n* keep   \n```\n";
+        assert_eq!(
+            format(text, &FormattingConfig::default()),
+            "- a\n- *b* and **c**\n\n```\n* keep   \n```\n"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+// src/headings.rs
+
+use crate::note::ParsedNote;
+
+/// A heading (`## Text`, or a setext heading underlined with `===` or `---`) found in a note.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    /// Zero-based line index of the heading (its first line for setext headings).
+    pub line: usize,
+    /// Heading level, from 1 to 6.
+    pub level: usize,
+    pub text: String,
+}
+
+/// Whether a line is an ATX heading: 1 to 6 `#` followed by a space or the end of line.
+pub fn is_heading_line(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    let rest = &trimmed[level..];
+    (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Normalizes heading text for anchor comparisons: case-insensitive, whitespace collapsed.
+pub fn normalize_anchor(anchor: &str) -> String {
+    anchor
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Finds the heading a `#Heading` anchor points at, as an index into `note.headings`.
+pub fn find_heading(note: &ParsedNote, anchor: &str) -> Option<usize> {
+    let anchor = normalize_anchor(anchor);
+    note.headings
+        .iter()
+        .position(|h| normalize_anchor(&h.value.text) == anchor)
+}
+
+/// Returns the lines of the section opened by `note.headings[index]`: the heading itself and
+/// everything up to the next heading of the same or a higher level.
+pub fn section_lines(note: &ParsedNote, index: usize) -> Vec<&str> {
+    let range = note.range(&note.section(index));
+    (range.start.line..=range.end.line)
+        .filter_map(|line| note.line(line as usize))
+        .collect()
+}
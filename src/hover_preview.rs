@@ -1,91 +1,185 @@
 // src/hover_preview.rs
 
+use crate::config::HoverConfig;
 use crate::db;
+use crate::headings;
+use crate::note::ParsedNote;
+use crate::position::PositionEncoding;
+use crate::wiki_links::{self, WikiLink};
 use log::error;
-use textwrap::{fill, Options};
+use std::time::SystemTime;
 use tokio::fs;
 use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind};
 
-/// Given a line of text and a column position, this asynchronous function checks for a valid wiki‑link.
-/// If one is found, it uses the provided database to search for a file whose virtual path matches.
-/// If the file is found, it reads the file using its local path and returns a Hover preview.
-pub async fn get_hover_preview(line: &str, col: usize, db: &db::Database) -> Option<Hover> {
-    // Attempt to parse a wiki‑link at the given column.
-    if let Some((_, _, virtual_path, _alias)) = parse_wiki_link(line, col) {
-        // Use the virtual path to search for the file in the database.
-        let file_infos = match db.get_all_file_infos().await {
-            Ok(infos) => infos,
-            Err(e) => {
-                error!("Error retrieving file infos from DB: {}", e);
-                return None;
+/// Given the wiki‑link under the cursor, this asynchronous function uses the provided database
+/// to search for a file whose virtual path matches.
+/// If the file is found, it reads the file using its local path and returns a Hover preview,
+/// rendered as markdown. For `[[note#Heading]]` links, only the section under that heading is
+/// previewed, and for `[[note#^id]]` links only the referenced block. The note title, its tags
+/// and its modification time are shown above the preview as configured.
+pub async fn get_hover_preview(
+    link: &WikiLink,
+    db: &db::Database,
+    config: &HoverConfig,
+) -> Option<Hover> {
+    // Use the virtual path to search for the file in the database.
+    let value = match find_file(db, &link.virtual_path).await {
+        // Use the local path (file.path) to read the file content.
+        Some(file) => match fs::read_to_string(&file.path).await {
+            Ok(content) => {
+                let modified = if config.show_modified {
+                    fs::metadata(&file.path)
+                        .await
+                        .and_then(|m| m.modified())
+                        .ok()
+                } else {
+                    None
+                };
+                let note = ParsedNote::parse(&content, PositionEncoding::Utf8);
+                let header = header(&note, &file, modified, config);
+                let preview = preview_markdown(&note, link.anchor.as_deref(), config);
+                if header.is_empty() {
+                    preview
+                } else {
+                    format!("{}\n\n---\n\n{}", header, preview)
+                }
             }
-        };
-        if let Some(file) = file_infos
-            .into_iter()
-            .find(|f| f.virtual_path == virtual_path)
-        {
-            // Use the local path (file.path) to read the file content.
-            match fs::read_to_string(&file.path).await {
-                Ok(content) => {
-                    // Limit preview length: for example, take the first 20 lines.
-                    let lines: Vec<&str> = content.lines().take(20).collect();
-                    let preview_text = lines.join("\n");
+            Err(_) => "Unable to read file content.".to_string(),
+        },
+        None => "Wiki-link target not found in database.".to_string(),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Looks up the note with the given virtual path in the database.
+pub async fn find_file(db: &db::Database, virtual_path: &str) -> Option<db::FileInfo> {
+    let file_infos = match db.get_all_file_infos().await {
+        Ok(infos) => infos,
+        Err(e) => {
+            error!("Error retrieving file infos from DB: {}", e);
+            return None;
+        }
+    };
+    file_infos
+        .into_iter()
+        .find(|f| f.virtual_path == virtual_path)
+}
 
-                    // Wrap the text to a fixed width (e.g., 80 characters) using textwrap.
-                    let options = Options::new(80);
-                    let wrapped_preview = fill(&preview_text, options);
+/// The previewed part of a note, as markdown: the section or block an anchor points at, or the
+/// note without its frontmatter. Past the configured line or character limit the preview is cut
+/// before the last heading, or else the last blank line, that fits.
+pub fn preview_markdown(note: &ParsedNote, anchor: Option<&str>, config: &HoverConfig) -> String {
+    let section = anchor
+        .and_then(|anchor| wiki_links::resolve_anchor(note, anchor))
+        .map(|target| target.lines);
+    let lines = section.unwrap_or_else(|| {
+        let body_start = note.frontmatter.as_ref().map_or(0, |f| f.span.end);
+        let body_line = note.range(&(body_start..body_start)).start.line as usize;
+        (body_line..).map_while(|line| note.line(line)).collect()
+    });
+    let lines: Vec<&str> = lines
+        .into_iter()
+        .skip_while(|line| line.trim().is_empty())
+        .collect();
 
-                    let hover_contents = HoverContents::Markup(MarkupContent {
-                        kind: MarkupKind::Markdown,
-                        value: format!("```markdown\n{}\n```", wrapped_preview),
-                    });
-                    return Some(Hover {
-                        contents: hover_contents,
-                        range: None,
-                    });
-                }
-                Err(_) => {
-                    return Some(Hover {
-                        contents: HoverContents::Markup(MarkupContent {
-                            kind: MarkupKind::Markdown,
-                            value: "Unable to read file content.".to_string(),
-                        }),
-                        range: None,
-                    });
-                }
-            }
-        } else {
-            return Some(Hover {
-                contents: HoverContents::Markup(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: "Wiki-link target not found in database.".to_string(),
-                }),
-                range: None,
-            });
+    let mut chars = 0;
+    let mut kept = lines
+        .iter()
+        .take(config.max_lines)
+        .take_while(|line| {
+            chars += line.chars().count() + 1;
+            chars <= config.max_chars
+        })
+        .count();
+    let truncated = kept < lines.len();
+    if truncated {
+        let boundary =
+            |is_boundary: fn(&str) -> bool| (1..kept).rev().find(|&i| is_boundary(lines[i]));
+        if let Some(cut) =
+            boundary(headings::is_heading_line).or_else(|| boundary(|line| line.trim().is_empty()))
+        {
+            kept = cut;
         }
     }
-    None
+
+    let mut preview = lines[..kept].join("\n").trim_end().to_string();
+    if kept == 0 {
+        // Even the first line is over the character limit.
+        preview = lines
+            .first()
+            .map(|line| line.chars().take(config.max_chars).collect())
+            .unwrap_or_default();
+    }
+    // A preview cut inside a code block would render the rest of the hover as code.
+    let fences = lines[..kept]
+        .iter()
+        .filter(|line| {
+            let line = line.trim_start();
+            line.starts_with("```") || line.starts_with("~~~")
+        })
+        .count();
+    if fences % 2 == 1 {
+        preview.push_str("\n```");
+    }
+    if truncated {
+        preview.push_str("\n\n…");
+    }
+    preview
 }
 
-/// Parses a wiki‑link from a given line at a specific column.
-/// Expected format: `[[virtual_path|alias]]`
-/// Returns (start_index, end_index, virtual_path, alias)
-pub fn parse_wiki_link(line: &str, col: usize) -> Option<(usize, usize, String, Option<String>)> {
-    let start = line[..col].rfind("[[")?;
-    let end = col + line[col..].find("]]")?;
-    if col < start || col > end + 2 {
-        return None;
+/// The lines shown above the preview: the title (from the frontmatter, or else the database),
+/// the tags and how long ago the note was modified, as enabled in `config`.
+fn header(
+    note: &ParsedNote,
+    file: &db::FileInfo,
+    modified: Option<SystemTime>,
+    config: &HoverConfig,
+) -> String {
+    let mut parts = Vec::new();
+    if config.show_title {
+        let title = note.title().unwrap_or_else(|| file.title.clone());
+        if !title.is_empty() {
+            parts.push(format!("**{}**", title));
+        }
     }
-    let content = &line[start + 2..end];
-    let parts: Vec<&str> = content.split('|').collect();
-    if parts.is_empty() {
-        return None;
+    if config.show_tags {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in &note.tags {
+            let tag = format!("`#{}`", tag.value.name);
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        if !tags.is_empty() {
+            parts.push(tags.join(" "));
+        }
+    }
+    if let Some(modified) = modified {
+        parts.push(format!("*Modified {}*", time_ago(modified)));
     }
-    let virtual_path = parts[0].trim().to_string();
-    let alias = if parts.len() > 1 {
-        Some(parts[1].trim().to_string())
-    } else {
-        None
+    parts.join("\n\n")
+}
+
+/// Describes how long ago `time` was, e.g. `3 days ago`.
+fn time_ago(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    let (count, unit) = match seconds {
+        0..60 => return "just now".to_string(),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86_400 => (seconds / 3600, "hour"),
+        86_400..2_592_000 => (seconds / 86_400, "day"),
+        2_592_000..31_536_000 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
     };
-    Some((start, end + 2, virtual_path, alias))
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
 }
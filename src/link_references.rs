@@ -1,13 +1,19 @@
 // src/link_references.rs
 
+use crate::note::ParsedNote;
+use crate::position::PositionEncoding;
 use regex::escape;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::Stdio;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::fs;
 use tokio::process::Command;
 use tokio::sync::RwLock;
 use tokio::time::Instant;
+use tower_lsp::lsp_types::Location;
+use url::Url;
 
 /// Our hybrid index maps a virtual path (String) to a tuple: (count, last_updated).
 pub type ReferencesMap = HashMap<String, (usize, Instant)>;
@@ -32,58 +38,119 @@ impl HybridIndex {
         }
     }
 
-    /// Query the reference count for a given virtual path.
+    /// Query the reference count for a given virtual path: the number of locations
+    /// [`HybridIndex::find_references`] returns, so that every feature shows the same count.
     /// If the cached value is fresh, it is returned immediately.
-    /// Otherwise, ripgrep is spawned to search the workspace, and the index is updated.
     pub async fn get_references_count(
         &self,
         virtual_path: &str,
+        open_notes: &HashMap<Url, Arc<ParsedNote>>,
+        encoding: PositionEncoding,
     ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
-        let now = Instant::now();
-
         // Check the in-memory index first.
         {
             let index = self.inner.read().await;
             if let Some(&(count, timestamp)) = index.get(virtual_path) {
-                if now.duration_since(timestamp) < self.freshness {
+                if Instant::now().duration_since(timestamp) < self.freshness {
                     return Ok(count);
                 }
             }
         }
 
-        // Fallback: use ripgrep to search the workspace.
-        let count = self.search_with_ripgrep(virtual_path).await?;
-        // Update the index.
-        {
-            let mut index = self.inner.write().await;
-            index.insert(virtual_path.to_string(), (count, now));
-        }
-        Ok(count)
+        // Fallback: search the workspace, which updates the index.
+        let locations = self
+            .find_references(virtual_path, open_notes, encoding)
+            .await?;
+        Ok(locations.len())
+    }
+
+    /// Drops the cached count of a virtual path, e.g. after the note was moved or deleted.
+    pub async fn invalidate(&self, virtual_path: &str) {
+        self.inner.write().await.remove(virtual_path);
     }
 
-    async fn search_with_ripgrep(
+    /// Returns the location of every wiki-link pointing at `virtual_path` in the workspace.
+    /// Open documents are searched in their current (possibly unsaved) state; for the other
+    /// files, ripgrep narrows the search down to those containing a match, which are then parsed
+    /// so each location carries an exact range in the negotiated encoding.
+    /// The cached reference count is refreshed as a side effect.
+    pub async fn find_references(
         &self,
         virtual_path: &str,
-    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
-        // Escape the virtual path to match it literally.
-        let escaped = escape(virtual_path);
-        // Build a regex pattern that matches wiki-links starting with the virtual path.
-        // Matches either: [[<virtual_path>]] or [[<virtual_path>|alias]]
-        let pattern = format!(r"\[\[\s*{}(\||\]\])", escaped);
+        open_notes: &HashMap<Url, Arc<ParsedNote>>,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<Location>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut locations = Vec::new();
+        for (uri, note) in open_notes {
+            locations.extend(link_locations(uri, note, virtual_path));
+        }
+        for file in self.files_with_references(virtual_path).await? {
+            let uri = match Url::from_file_path(&file) {
+                Ok(u) => u,
+                Err(_) => {
+                    log::error!("Could not convert local path {} to URI", file.display());
+                    continue;
+                }
+            };
+            if open_notes.contains_key(&uri) {
+                continue;
+            }
+            match fs::read_to_string(&file).await {
+                Ok(content) => {
+                    let note = ParsedNote::parse(&content, encoding);
+                    locations.extend(link_locations(&uri, &note, virtual_path))
+                }
+                Err(e) => log::error!("Could not read {}: {}", file.display(), e),
+            }
+        }
+
+        let mut index = self.inner.write().await;
+        index.insert(virtual_path.to_string(), (locations.len(), Instant::now()));
+        Ok(locations)
+    }
 
+    /// Lists the (absolute) paths of the files that contain at least one wiki-link to
+    /// `virtual_path`.
+    pub async fn files_with_references(
+        &self,
+        virtual_path: &str,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
         let output = Command::new("rg")
-            .arg("-o") // Only output matching parts.
-            .arg("--no-heading")
-            .arg("--line-number")
-            .arg(&pattern)
+            .arg("-l") // Only list the matching files.
+            .arg(reference_pattern(virtual_path))
             .arg(&self.workspace_root)
             .stdout(Stdio::piped())
             .output()
             .await?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
-        // Each matching line is one reference.
-        let count = stdout.lines().count();
-        Ok(count)
+        let mut files = Vec::new();
+        for line in stdout.lines() {
+            match fs::canonicalize(line).await {
+                Ok(path) => files.push(path),
+                Err(e) => log::error!("Could not resolve path {}: {}", line, e),
+            }
+        }
+        Ok(files)
     }
 }
+
+/// The locations of the wiki-links to `virtual_path` in `note`, the content of `uri`.
+fn link_locations(uri: &Url, note: &ParsedNote, virtual_path: &str) -> Vec<Location> {
+    note.wiki_links
+        .iter()
+        .filter(|link| link.value.virtual_path == virtual_path)
+        .map(|link| Location {
+            uri: uri.clone(),
+            range: link.range,
+        })
+        .collect()
+}
+
+/// Builds a regex pattern that matches wiki-links starting with the virtual path.
+/// Matches [[<virtual_path>]], [[<virtual_path>|alias]] and [[<virtual_path>#Heading]]
+fn reference_pattern(virtual_path: &str) -> String {
+    // Escape the virtual path to match it literally.
+    let escaped = escape(virtual_path);
+    format!(r"\[\[\s*{}\s*(\||#|\]\])", escaped)
+}
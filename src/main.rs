@@ -1,11 +1,31 @@
 // src/main.rs
 
+mod blocks;
+mod code_actions;
+mod completion;
+mod config;
 mod db;
+mod diagnostics;
+mod document_highlight;
+mod document_links;
+mod document_store;
 mod document_symbols;
+mod folding_ranges;
+mod formatting;
 mod goto_definition;
+mod headings;
 mod hover_preview;
 mod link_references;
+mod note;
+mod note_index;
+mod on_type_formatting;
+mod position;
+mod rename;
+mod selection_range;
+mod semantic_tokens;
 mod server;
+mod tags;
+mod wiki_links;
 mod workspace_symbols;
 
 #[tokio::main]
@@ -0,0 +1,745 @@
+// src/note.rs
+
+use crate::blocks::{self, BlockId};
+use crate::headings::Heading;
+use crate::position::{LineIndex, PositionEncoding};
+use crate::wiki_links::{self, WikiLink};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use regex::Regex;
+use std::ops;
+use std::sync::OnceLock;
+use tower_lsp::lsp_types::{Position, Range};
+
+/// An element of a note with its location, both as byte offsets into the text and as an LSP
+/// range in the negotiated encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: ops::Range<usize>,
+    pub range: Range,
+}
+
+/// Byte ranges of the parts of a wiki-link, surrounding whitespace excluded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLinkParts {
+    /// The virtual path.
+    pub target: ops::Range<usize>,
+    /// The anchor, from its `#`.
+    pub anchor: Option<ops::Range<usize>>,
+    /// The `|` introducing the alias.
+    pub pipe: Option<usize>,
+    pub alias: Option<ops::Range<usize>>,
+}
+
+/// A `[text](destination)` link. Images are not links and are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownLink {
+    pub destination: String,
+}
+
+/// The YAML block at the very top of a note, between `---` lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frontmatter {
+    /// The YAML source, without the delimiters.
+    pub yaml: String,
+    /// The parsed YAML, or `None` when it is malformed.
+    pub value: Option<serde_yaml::Value>,
+}
+
+/// A `#tag` in the body of a note, or an entry of the `tags` frontmatter key. Body tags span
+/// their `#`; frontmatter tags span the name only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteTag {
+    /// The tag without its `#`, e.g. `project/alpha`.
+    pub name: String,
+    pub in_frontmatter: bool,
+}
+
+/// A `[^label]` footnote reference, or the `[^label]:` opening a footnote definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    pub label: String,
+    pub definition: bool,
+}
+
+/// A note parsed once with pulldown-cmark: the elements every feature works with, located in
+/// the text. Wiki-links, tags and markdown links inside code spans and code blocks are not
+/// part of the model, so all features ignore them alike.
+#[derive(Debug, Clone)]
+pub struct ParsedNote {
+    pub text: String,
+    pub encoding: PositionEncoding,
+    lines: LineIndex,
+    pub frontmatter: Option<Spanned<Frontmatter>>,
+    pub headings: Vec<Spanned<Heading>>,
+    pub wiki_links: Vec<Spanned<WikiLink>>,
+    pub markdown_links: Vec<Spanned<MarkdownLink>>,
+    pub tags: Vec<Spanned<NoteTag>>,
+    /// `^block-id` markers, spanning the `^` and the id.
+    pub block_ids: Vec<Spanned<BlockId>>,
+    /// Footnote references and definitions, spanning the `[^label]`.
+    pub footnotes: Vec<Spanned<Footnote>>,
+    /// Byte ranges of the code blocks, fences included.
+    pub code_blocks: Vec<ops::Range<usize>>,
+    /// Byte ranges of the inline code spans, backticks included.
+    pub code_spans: Vec<ops::Range<usize>>,
+    /// Byte ranges of the block quotes, callouts included.
+    pub block_quotes: Vec<ops::Range<usize>>,
+    pub paragraphs: Vec<ops::Range<usize>>,
+    /// Byte ranges of the emphasis spans, delimiters included.
+    pub emphasis: Vec<ops::Range<usize>>,
+    pub strong: Vec<ops::Range<usize>>,
+    pub strikethrough: Vec<ops::Range<usize>>,
+    pub tables: Vec<ops::Range<usize>>,
+    pub list_items: Vec<ops::Range<usize>>,
+    pub html_comments: Vec<ops::Range<usize>>,
+}
+
+fn tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"#([\p{L}\p{N}_/-]+)").unwrap())
+}
+
+/// The markdown extensions notes are parsed with.
+pub fn parser_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options
+}
+
+impl ParsedNote {
+    pub fn parse(text: &str, encoding: PositionEncoding) -> Self {
+        let mut note = ParsedNote {
+            text: text.to_string(),
+            encoding,
+            lines: LineIndex::new(text),
+            frontmatter: None,
+            headings: Vec::new(),
+            wiki_links: Vec::new(),
+            markdown_links: Vec::new(),
+            tags: Vec::new(),
+            block_ids: Vec::new(),
+            footnotes: Vec::new(),
+            code_blocks: Vec::new(),
+            code_spans: Vec::new(),
+            block_quotes: Vec::new(),
+            paragraphs: Vec::new(),
+            emphasis: Vec::new(),
+            strong: Vec::new(),
+            strikethrough: Vec::new(),
+            tables: Vec::new(),
+            list_items: Vec::new(),
+            html_comments: Vec::new(),
+        };
+
+        // Heading being read: level, text and byte range.
+        let mut heading: Option<(usize, String, ops::Range<usize>)> = None;
+        // Frontmatter being read: byte range of the YAML and of the whole block.
+        let mut metadata: Option<(Option<ops::Range<usize>>, ops::Range<usize>)> = None;
+        let mut in_code_block = false;
+
+        for (event, range) in Parser::new_ext(text, parser_options()).into_offset_iter() {
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    heading = Some((heading_level(level), String::new(), range));
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    if let Some((level, name, range)) = heading.take() {
+                        let end = trim_end_offset(text, range.end);
+                        let span = range.start..end;
+                        let heading = Heading {
+                            line: note.lines.line_of(range.start),
+                            level,
+                            text: name.trim().to_string(),
+                        };
+                        note.headings.push(note.spanned(heading, span));
+                    }
+                }
+                Event::Start(Tag::MetadataBlock(_)) => metadata = Some((None, range)),
+                Event::End(TagEnd::MetadataBlock(_)) => {
+                    if let Some((yaml, block)) = metadata.take() {
+                        let yaml = yaml.unwrap_or(block.start..block.start);
+                        note.add_frontmatter(yaml, block);
+                    }
+                }
+                Event::Start(Tag::CodeBlock(_)) => {
+                    in_code_block = true;
+                    note.code_blocks.push(range);
+                }
+                Event::End(TagEnd::CodeBlock) => in_code_block = false,
+                Event::Code(code) => {
+                    if let Some((_, name, _)) = heading.as_mut() {
+                        name.push_str(&code);
+                    }
+                    note.code_spans.push(range);
+                }
+                Event::Start(Tag::BlockQuote(_)) => note.block_quotes.push(range),
+                Event::Start(Tag::Paragraph) => note.paragraphs.push(range),
+                Event::Start(Tag::Emphasis) => note.emphasis.push(range),
+                Event::Start(Tag::Strong) => note.strong.push(range),
+                Event::Start(Tag::Strikethrough) => note.strikethrough.push(range),
+                Event::Start(Tag::Table(_)) => note.tables.push(range),
+                Event::Start(Tag::Item) => note.list_items.push(range),
+                Event::Start(Tag::HtmlBlock) | Event::InlineHtml(_)
+                    if text[range.clone()].trim_start().starts_with("<!--") =>
+                {
+                    note.html_comments.push(range);
+                }
+                Event::Text(t) => {
+                    if let Some((yaml, _)) = metadata.as_mut() {
+                        let start = yaml.as_ref().map_or(range.start, |y| y.start);
+                        *yaml = Some(start..range.end);
+                        continue;
+                    }
+                    if in_code_block {
+                        continue;
+                    }
+                    if let Some((_, name, _)) = heading.as_mut() {
+                        name.push_str(&t);
+                    }
+                    note.add_body_tags(range);
+                }
+                Event::FootnoteReference(label) => {
+                    let footnote = Footnote {
+                        label: label.to_string(),
+                        definition: false,
+                    };
+                    note.footnotes.push(note.spanned(footnote, range));
+                }
+                Event::Start(Tag::FootnoteDefinition(label)) => {
+                    let end = text[range.clone()]
+                        .find(']')
+                        .map_or(range.end, |i| range.start + i + 1);
+                    let footnote = Footnote {
+                        label: label.to_string(),
+                        definition: true,
+                    };
+                    note.footnotes
+                        .push(note.spanned(footnote, range.start..end));
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some((_, name, _)) = heading.as_mut() {
+                        name.push(' ');
+                    }
+                }
+                Event::Start(Tag::Link { dest_url, .. }) => {
+                    let link = MarkdownLink {
+                        destination: dest_url.to_string(),
+                    };
+                    note.markdown_links.push(note.spanned(link, range));
+                }
+                _ => {}
+            }
+        }
+
+        for link in wiki_links::find_wiki_links(text, &note.lines) {
+            let line_start = note.lines.line_start(link.line).unwrap_or_default();
+            let span = line_start + link.start..line_start + link.end;
+            if note.overlaps_code(&span) {
+                continue;
+            }
+            note.wiki_links.push(note.spanned(link, span));
+        }
+        for block in blocks::find_block_ids(text, &note.lines) {
+            let line_start = note.lines.line_start(block.line).unwrap_or_default();
+            let span = line_start + block.start..line_start + block.end;
+            let in_frontmatter = note
+                .frontmatter
+                .as_ref()
+                .is_some_and(|f| overlaps(&f.span, &span));
+            if note.overlaps_code(&span) || in_frontmatter {
+                continue;
+            }
+            note.block_ids.push(note.spanned(block, span));
+        }
+        // `[[note]]` can also parse as a shortcut reference link; it is a wiki-link only.
+        let wiki_spans: Vec<ops::Range<usize>> =
+            note.wiki_links.iter().map(|l| l.span.clone()).collect();
+        note.markdown_links
+            .retain(|l| !wiki_spans.iter().any(|w| overlaps(w, &l.span)));
+        note
+    }
+
+    fn spanned<T>(&self, value: T, span: ops::Range<usize>) -> Spanned<T> {
+        Spanned {
+            range: self.range(&span),
+            value,
+            span,
+        }
+    }
+
+    /// Converts a byte range of the text to an LSP range.
+    pub fn range(&self, span: &ops::Range<usize>) -> Range {
+        self.lines
+            .range(&self.text, span.start, span.end, self.encoding)
+    }
+
+    /// Converts an LSP position to a byte offset into the text.
+    pub fn offset(&self, pos: Position) -> usize {
+        self.lines.offset(&self.text, pos, self.encoding)
+    }
+
+    /// Returns a line of the note without its line ending.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.lines.line(&self.text, line)
+    }
+
+    /// Whether the byte offset lies inside a code span or code block.
+    pub fn in_code(&self, byte: usize) -> bool {
+        self.code_blocks
+            .iter()
+            .chain(&self.code_spans)
+            .any(|c| c.start <= byte && byte < c.end)
+    }
+
+    fn overlaps_code(&self, span: &ops::Range<usize>) -> bool {
+        self.code_blocks
+            .iter()
+            .chain(&self.code_spans)
+            .any(|c| overlaps(c, span))
+    }
+
+    /// Returns the wiki-link under the cursor, if any.
+    pub fn wiki_link_at(&self, pos: Position) -> Option<&Spanned<WikiLink>> {
+        let offset = self.offset(pos);
+        self.wiki_links
+            .iter()
+            .find(|l| l.span.start <= offset && offset <= l.span.end)
+    }
+
+    /// Returns the tag under the cursor, if any.
+    pub fn tag_at(&self, pos: Position) -> Option<&Spanned<NoteTag>> {
+        let offset = self.offset(pos);
+        self.tags
+            .iter()
+            .find(|t| t.span.start <= offset && offset <= t.span.end)
+    }
+
+    /// Splits the wiki-link spanning `span` (`[[path#anchor|alias]]`) into its parts.
+    pub fn wiki_link_parts(&self, span: &ops::Range<usize>) -> WikiLinkParts {
+        let inner = span.start + 2..span.end - 2;
+        let content = &self.text[inner.clone()];
+        let target_end = content.find('|').map_or(inner.end, |i| inner.start + i);
+        let path_end = self.text[inner.start..target_end]
+            .find('#')
+            .map_or(target_end, |i| inner.start + i);
+        WikiLinkParts {
+            target: self.trimmed(inner.start..path_end),
+            anchor: (path_end < target_end)
+                .then(|| path_end..self.trimmed(path_end..target_end).end),
+            pipe: (target_end < inner.end).then_some(target_end),
+            alias: (target_end < inner.end).then(|| self.trimmed(target_end + 1..inner.end)),
+        }
+    }
+
+    /// Shrinks a byte range to exclude surrounding whitespace.
+    pub fn trimmed(&self, span: ops::Range<usize>) -> ops::Range<usize> {
+        let slice = &self.text[span.clone()];
+        let start = span.start + (slice.len() - slice.trim_start().len());
+        let end = span.start + slice.trim_end().len();
+        start..end.max(start)
+    }
+
+    /// Byte range of the section opened by `self.headings[index]`: the heading and everything
+    /// up to the next heading of the same or a higher level, trailing blank lines excluded.
+    pub fn section(&self, index: usize) -> ops::Range<usize> {
+        let heading = &self.headings[index];
+        let end = self.headings[index + 1..]
+            .iter()
+            .find(|h| h.value.level <= heading.value.level)
+            .map(|h| h.span.start)
+            .unwrap_or(self.text.len());
+        heading.span.start..heading.span.end.max(trim_end_offset(&self.text, end))
+    }
+
+    /// The `title` frontmatter key, when set to a non-empty string.
+    pub fn title(&self) -> Option<String> {
+        let value = self.frontmatter.as_ref()?.value.value.as_ref()?;
+        let title = value.get("title")?.as_str()?.trim();
+        (!title.is_empty()).then(|| title.to_string())
+    }
+
+    /// The entries of the `aliases` (or `alias`) frontmatter key, a list or a single string.
+    pub fn aliases(&self) -> Vec<String> {
+        let value = self
+            .frontmatter
+            .as_ref()
+            .and_then(|f| f.value.value.as_ref());
+        let Some(aliases) = value.and_then(|v| v.get("aliases").or_else(|| v.get("alias"))) else {
+            return Vec::new();
+        };
+        let names: Vec<&str> = match aliases {
+            serde_yaml::Value::Sequence(items) => {
+                items.iter().filter_map(serde_yaml::Value::as_str).collect()
+            }
+            serde_yaml::Value::String(s) => vec![s.as_str()],
+            _ => Vec::new(),
+        };
+        names
+            .into_iter()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Records the `#tags` of a text fragment of the body. A tag starts at the beginning of a
+    /// line or after whitespace and contains at least one character that is not a digit.
+    fn add_body_tags(&mut self, range: ops::Range<usize>) {
+        let fragment = &self.text[range.clone()];
+        let mut tags = Vec::new();
+        for caps in tag_regex().captures_iter(fragment) {
+            let whole = caps.get(0).unwrap();
+            let start = range.start + whole.start();
+            let preceded_by_space = self.text[..start]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace);
+            let name = &caps[1];
+            if !preceded_by_space || name.chars().all(|c| c.is_ascii_digit()) {
+                continue;
+            }
+            let tag = NoteTag {
+                name: name.trim_end_matches('/').to_string(),
+                in_frontmatter: false,
+            };
+            tags.push(self.spanned(tag, start..range.start + whole.end()));
+        }
+        self.tags.extend(tags);
+    }
+
+    /// Records the frontmatter block and the tags listed under its `tags` key.
+    fn add_frontmatter(&mut self, yaml: ops::Range<usize>, block: ops::Range<usize>) {
+        let yaml_start = yaml.start;
+        let yaml = self.text[yaml].to_string();
+        let value = serde_yaml::from_str::<serde_yaml::Value>(&yaml).ok();
+
+        // Each tag is located among the scalars written in the value of the tags key, in order.
+        let scalars = tags_key(value.as_ref())
+            .and_then(|key| key_value_span(&yaml, key))
+            .map(|span| yaml_scalars(&yaml, span))
+            .unwrap_or_default();
+        let mut next = 0;
+        let mut tags = Vec::new();
+        for name in frontmatter_tags(value.as_ref()) {
+            let Some(found) = scalars[next..].iter().position(|s| yaml[s.clone()] == name) else {
+                continue;
+            };
+            let end = yaml_start + scalars[next + found].end;
+            next += found + 1;
+            // The span covers the name only, even when it is written with its `#`.
+            let name = name.trim_start_matches('#');
+            let tag = NoteTag {
+                name: name.to_string(),
+                in_frontmatter: true,
+            };
+            tags.push(self.spanned(tag, end - name.len()..end));
+        }
+        self.tags.extend(tags);
+        self.frontmatter = Some(self.spanned(Frontmatter { yaml, value }, block));
+    }
+}
+
+/// The frontmatter key listing the tags of a note: `tags`, or `tag` when there is no `tags`.
+fn tags_key(value: Option<&serde_yaml::Value>) -> Option<&'static str> {
+    let value = value?;
+    ["tags", "tag"]
+        .into_iter()
+        .find(|key| value.get(key).is_some())
+}
+
+/// The entries of the `tags` (or `tag`) frontmatter key, which holds either a list or a string
+/// of tags separated by commas or spaces.
+fn frontmatter_tags(value: Option<&serde_yaml::Value>) -> Vec<String> {
+    let Some(tags) = tags_key(value).and_then(|key| value?.get(key)) else {
+        return Vec::new();
+    };
+    let names: Vec<String> = match tags {
+        serde_yaml::Value::Sequence(items) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        serde_yaml::Value::String(s) => s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    };
+    names
+        .into_iter()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.trim_start_matches('#').is_empty())
+        .collect()
+}
+
+/// Byte range of the value of a top-level `key` of a YAML mapping: the rest of the line of the
+/// key, and the following lines that are indented or are items of a block list.
+fn key_value_span(yaml: &str, key: &str) -> Option<ops::Range<usize>> {
+    let mut start = None;
+    let mut offset = 0;
+    for line in yaml.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        match start {
+            None => {
+                let Some(rest) = line.strip_prefix(key) else {
+                    continue;
+                };
+                if let Some(value) = rest.trim_start_matches([' ', '\t']).strip_prefix(':') {
+                    start = Some(offset - value.len());
+                }
+            }
+            Some(start) => {
+                let blank = line.trim().is_empty();
+                if !blank && !line.starts_with([' ', '\t', '-']) {
+                    return Some(start..line_start);
+                }
+            }
+        }
+    }
+    start.map(|start| start..yaml.len())
+}
+
+/// Byte ranges of the scalars written in `span` of a YAML source: the words of plain scalars
+/// (split at whitespace, commas and brackets) and quoted strings, both as a whole and split into
+/// words. Comments are skipped. The ranges are ordered by position.
+fn yaml_scalars(yaml: &str, span: ops::Range<usize>) -> Vec<ops::Range<usize>> {
+    let mut scalars = Vec::new();
+    let mut word: Option<usize> = None;
+    // Quote character and start of the quoted content.
+    let mut quote: Option<(char, usize)> = None;
+    let mut in_comment = false;
+    let mut previous = ' ';
+    let end_word = |word: &mut Option<usize>, end: usize, scalars: &mut Vec<_>| {
+        if let Some(start) = word.take() {
+            scalars.push(start..end);
+        }
+    };
+    for (i, c) in yaml[span.clone()].char_indices() {
+        let at = span.start + i;
+        if in_comment {
+            in_comment = c != '\n';
+        } else if let Some((_, start)) = quote.filter(|&(q, _)| c == q) {
+            end_word(&mut word, at, &mut scalars);
+            let content = &yaml[start..at];
+            let leading = content.len() - content.trim_start().len();
+            scalars.push(start + leading..start + content.trim_end().len().max(leading));
+            quote = None;
+        } else if c.is_whitespace() || matches!(c, ',' | '[' | ']') {
+            end_word(&mut word, at, &mut scalars);
+        } else if quote.is_none() && word.is_none() && c == '#' && previous.is_whitespace() {
+            in_comment = true;
+        } else if quote.is_none() && word.is_none() && matches!(c, '"' | '\'') {
+            quote = Some((c, at + c.len_utf8()));
+        } else if word.is_none() {
+            word = Some(at);
+        }
+        previous = c;
+    }
+    end_word(&mut word, span.end, &mut scalars);
+    scalars.sort_by_key(|s| (s.start, std::cmp::Reverse(s.end)));
+    scalars
+}
+
+fn overlaps(a: &ops::Range<usize>, b: &ops::Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+fn heading_level(level: HeadingLevel) -> usize {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Moves a byte offset back over trailing whitespace and line breaks, so ranges end on the last
+/// line with content.
+fn trim_end_offset(text: &str, end: usize) -> usize {
+    text[..end].trim_end().len()
+}
+
+#[cfg(test)]
+impl ParsedNote {
+    /// Applies edits made against this note, e.g. formatting edits, and returns the new text.
+    pub fn apply_edits(&self, edits: &[tower_lsp::lsp_types::TextEdit]) -> String {
+        let mut spans: Vec<(ops::Range<usize>, &str)> = edits
+            .iter()
+            .map(|e| {
+                (
+                    self.offset(e.range.start)..self.offset(e.range.end),
+                    e.new_text.as_str(),
+                )
+            })
+            .collect();
+        spans.sort_by_key(|(span, _)| span.start);
+        let mut text = self.text.clone();
+        for (span, new_text) in spans.into_iter().rev() {
+            text.replace_range(span, new_text);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> ParsedNote {
+        ParsedNote::parse(text, PositionEncoding::Utf16)
+    }
+
+    /// The frontmatter tags of a note, with the text their span covers.
+    fn tag_texts(text: &str) -> Vec<(String, &str)> {
+        parse(text)
+            .tags
+            .into_iter()
+            .filter(|t| t.value.in_frontmatter)
+            .map(|t| (t.value.name, &text[t.span]))
+            .collect()
+    }
+
+    fn owned(tags: &[(&str, &'static str)]) -> Vec<(String, &'static str)> {
+        tags.iter().map(|(n, t)| (n.to_string(), *t)).collect()
+    }
+
+    #[test]
+    fn flow_lists_of_tags_are_located_in_the_value() {
+        let text = "---\ntags: [a, \"b/c\", '#d']\n---\n";
+        let tags = tag_texts(text);
+        assert_eq!(tags, owned(&[("a", "a"), ("b/c", "b/c"), ("d", "d")]));
+        let spans: Vec<usize> = parse(text).tags.iter().map(|t| t.span.start).collect();
+        assert_eq!(spans, vec![11, 15, 23]);
+    }
+
+    #[test]
+    fn block_lists_of_tags_are_located_in_the_value() {
+        let starts = |text: &str| -> Vec<(String, usize)> {
+            parse(text)
+                .tags
+                .into_iter()
+                .map(|t| (t.value.name, t.span.start))
+                .collect()
+        };
+        let text = "---\ntags:\n  - a\n  - tags # a comment\ntitle: a\n---\n";
+        assert_eq!(
+            starts(text),
+            vec![("a".to_string(), 14), ("tags".to_string(), 20)]
+        );
+        let text = "---\ntags:\n- a\n- b\n---\n";
+        assert_eq!(
+            starts(text),
+            vec![("a".to_string(), 12), ("b".to_string(), 16)]
+        );
+    }
+
+    #[test]
+    fn the_tag_key_is_supported() {
+        let text = "---\ntitle: project plan\ntag: project\n---\n";
+        let note = parse(text);
+        assert_eq!(note.tags.len(), 1);
+        assert_eq!(note.tags[0].span, 29..36);
+        assert_eq!(note.tags[0].range.start, Position::new(2, 5));
+    }
+
+    #[test]
+    fn tag_strings_are_split_at_commas_and_spaces() {
+        let text = "---\ntags: \"a, #b c\"\n---\n";
+        assert_eq!(
+            tag_texts(text),
+            owned(&[("a", "a"), ("b", "b"), ("c", "c")])
+        );
+        let text = "---\ntags: a, a/b\n---\n";
+        let spans: Vec<usize> = parse(text).tags.iter().map(|t| t.span.start).collect();
+        assert_eq!(spans, vec![10, 13]);
+    }
+
+    #[test]
+    fn a_lone_carriage_return_ends_a_line() {
+        let text = "a\rb\n[[x]] ^id\n";
+        let note = parse(text);
+        let link = &note.wiki_links[0];
+        assert_eq!(&text[link.span.clone()], "[[x]]");
+        assert_eq!(link.range.start, Position::new(2, 0));
+        let block = &note.block_ids[0];
+        assert_eq!(&text[block.span.clone()], "^id");
+        assert_eq!(block.value.line, 2);
+
+        let note = parse("x\nfoo\rééééé\nab ^id\n");
+        assert_eq!(note.block_ids[0].value.line, 3);
+        assert!(!blocks::is_standalone(&note, &note.block_ids[0].value));
+    }
+
+    #[test]
+    fn links_tags_and_block_ids_in_code_are_ignored() {
+        let note = parse("`[[a]]` and [[b]] #yes\n```\n[[c]] #tag ^id\n```\n");
+        let links: Vec<&str> = note
+            .wiki_links
+            .iter()
+            .map(|l| l.value.virtual_path.as_str())
+            .collect();
+        assert_eq!(links, ["b"]);
+        let tags: Vec<&str> = note.tags.iter().map(|t| t.value.name.as_str()).collect();
+        assert_eq!(tags, ["yes"]);
+        assert!(note.block_ids.is_empty());
+        assert_eq!(note.code_spans, vec![0..7]);
+        assert_eq!(note.code_blocks, vec![23..45]);
+    }
+
+    #[test]
+    fn heading_text_is_the_rendered_text() {
+        let note = parse("# Title with `code` and *em*\nSetext\n===\n## Trailing ##\n");
+        let headings: Vec<(usize, usize, &str)> = note
+            .headings
+            .iter()
+            .map(|h| (h.value.line, h.value.level, h.value.text.as_str()))
+            .collect();
+        assert_eq!(
+            headings,
+            [
+                (0, 1, "Title with code and em"),
+                (1, 1, "Setext"),
+                (3, 2, "Trailing")
+            ]
+        );
+        assert_eq!(note.headings[1].span, 29..39);
+    }
+
+    #[test]
+    fn footnotes_span_their_label() {
+        let text = "Text[^1] more.\n\n[^1]: The note.\n";
+        let note = parse(text);
+        let footnotes: Vec<(&str, bool, &str)> = note
+            .footnotes
+            .iter()
+            .map(|f| {
+                (
+                    f.value.label.as_str(),
+                    f.value.definition,
+                    &text[f.span.clone()],
+                )
+            })
+            .collect();
+        assert_eq!(footnotes, [("1", false, "[^1]"), ("1", true, "[^1]")]);
+        assert_eq!(note.footnotes[1].range.start, Position::new(2, 0));
+    }
+
+    #[test]
+    fn block_ids_outside_the_frontmatter_are_found() {
+        let text = "---\nkey: a ^fm\n---\nPara ^abc\n- item ^def\n";
+        let note = parse(text);
+        let ids: Vec<(&str, usize, &str)> = note
+            .block_ids
+            .iter()
+            .map(|b| (b.value.id.as_str(), b.value.line, &text[b.span.clone()]))
+            .collect();
+        assert_eq!(ids, [("abc", 3, "^abc"), ("def", 4, "^def")]);
+    }
+}
@@ -0,0 +1,309 @@
+// src/note_index.rs
+
+use crate::db;
+use crate::document_store::DocumentStore;
+use crate::note::{NoteTag, ParsedNote, Spanned};
+use crate::position::PositionEncoding;
+use futures::future::join_all;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::fs;
+use tokio::sync::RwLock;
+use url::Url;
+
+/// What vault-wide features need to know about a note, taken from its text.
+#[derive(Debug)]
+pub struct NoteSummary {
+    pub tags: Vec<Spanned<NoteTag>>,
+    /// The `title` frontmatter key.
+    pub title: Option<String>,
+    /// The `aliases` frontmatter key: other names `[[Alias]]` links may use for the note.
+    pub aliases: Vec<String>,
+}
+
+impl NoteSummary {
+    fn new(note: &ParsedNote) -> Self {
+        Self {
+            title: note.title(),
+            aliases: note.aliases(),
+            tags: note.tags.clone(),
+        }
+    }
+}
+
+/// A note of the `files` table with its summary.
+pub struct IndexedNote {
+    /// The database record, with the frontmatter title in place of the database one when the
+    /// note has one.
+    pub info: db::FileInfo,
+    pub uri: Url,
+    /// Last modification of the file on disk, if known.
+    pub modified: Option<SystemTime>,
+    pub summary: Arc<NoteSummary>,
+}
+
+/// A note of the `files` table as last read from disk.
+struct VaultNote {
+    /// The database record, title included.
+    info: db::FileInfo,
+    uri: Url,
+    modified: Option<SystemTime>,
+    summary: Arc<NoteSummary>,
+}
+
+impl VaultNote {
+    fn indexed(&self, summary: Arc<NoteSummary>) -> IndexedNote {
+        let mut info = self.info.clone();
+        if let Some(title) = &summary.title {
+            info.title = title.clone();
+        }
+        IndexedNote {
+            info,
+            uri: self.uri.clone(),
+            modified: self.modified,
+            summary,
+        }
+    }
+}
+
+/// Summaries of the notes of the vault. The list of notes is built on first use and kept until
+/// [`NoteIndex::invalidate`]: completion asks for it on every keystroke, far more often than
+/// notes are saved, created, moved or deleted. When it is rebuilt, notes are only read and
+/// parsed again if their modification time changed.
+#[derive(Default)]
+pub struct NoteIndex {
+    files: RwLock<HashMap<String, CachedSummary>>,
+    vault: RwLock<Option<(PositionEncoding, Arc<Vec<VaultNote>>)>>,
+    /// The aliases of the vault, built along with the list of notes.
+    aliases: RwLock<Option<Arc<HashMap<String, String>>>>,
+}
+
+struct CachedSummary {
+    modified: SystemTime,
+    encoding: PositionEncoding,
+    summary: Arc<NoteSummary>,
+}
+
+impl NoteIndex {
+    /// Returns every note of the `files` table. Open documents are summarized from their parsed
+    /// form in `documents`, so unsaved changes are taken into account.
+    pub async fn notes(
+        &self,
+        db: &db::Database,
+        documents: &DocumentStore,
+        encoding: PositionEncoding,
+    ) -> Vec<IndexedNote> {
+        let vault = self.vault(db, encoding).await;
+        let open: HashMap<Url, Arc<NoteSummary>> = documents
+            .notes(encoding)
+            .await
+            .into_iter()
+            .map(|(uri, note)| (uri, Arc::new(NoteSummary::new(&note))))
+            .collect();
+        vault
+            .iter()
+            .map(|note| {
+                let summary = open.get(&note.uri).unwrap_or(&note.summary);
+                note.indexed(summary.clone())
+            })
+            .collect()
+    }
+
+    /// Maps each alias to the virtual path of the note declaring it, as [`aliases`] does. The
+    /// map is cached until [`NoteIndex::invalidate`].
+    pub async fn aliases(
+        &self,
+        db: &db::Database,
+        documents: &DocumentStore,
+        encoding: PositionEncoding,
+    ) -> Arc<HashMap<String, String>> {
+        if let Some(aliases) = self.aliases.read().await.as_ref() {
+            return aliases.clone();
+        }
+        let aliases = Arc::new(aliases(&self.notes(db, documents, encoding).await));
+        *self.aliases.write().await = Some(aliases.clone());
+        aliases
+    }
+
+    /// Drops the cached list of notes and aliases, after a note was saved, created, moved or
+    /// deleted.
+    pub async fn invalidate(&self) {
+        *self.vault.write().await = None;
+        *self.aliases.write().await = None;
+    }
+
+    /// The notes of the `files` table as found on disk, from the cache when it is still valid.
+    async fn vault(&self, db: &db::Database, encoding: PositionEncoding) -> Arc<Vec<VaultNote>> {
+        if let Some((cached_encoding, vault)) = self.vault.read().await.as_ref() {
+            if *cached_encoding == encoding {
+                return vault.clone();
+            }
+        }
+        let infos = match db.get_all_file_infos().await {
+            Ok(infos) => infos,
+            Err(e) => {
+                log::error!("Error retrieving file infos from DB: {}", e);
+                return Arc::default();
+            }
+        };
+        let tasks = infos.into_iter().map(|info| async move {
+            let Ok(uri) = Url::from_file_path(&info.path) else {
+                log::error!("Could not convert local path {} to URI", info.path);
+                return None;
+            };
+            let modified = fs::metadata(&info.path)
+                .await
+                .and_then(|m| m.modified())
+                .ok();
+            let summary = self.summary(&info.path, modified?, encoding).await?;
+            Some(VaultNote {
+                info,
+                uri,
+                modified,
+                summary,
+            })
+        });
+        let vault: Arc<Vec<VaultNote>> =
+            Arc::new(join_all(tasks).await.into_iter().flatten().collect());
+        *self.vault.write().await = Some((encoding, vault.clone()));
+        vault
+    }
+
+    /// The title of a note: its `title` frontmatter key, or else its database title. The note
+    /// is read from disk, through the cache.
+    pub async fn title(&self, info: &db::FileInfo, encoding: PositionEncoding) -> String {
+        let modified = fs::metadata(&info.path)
+            .await
+            .and_then(|m| m.modified())
+            .ok();
+        let summary = match modified {
+            Some(modified) => self.summary(&info.path, modified, encoding).await,
+            None => None,
+        };
+        summary
+            .and_then(|s| s.title.clone())
+            .unwrap_or_else(|| info.title.clone())
+    }
+
+    /// The summary of a note on disk, from the cache when the file did not change.
+    async fn summary(
+        &self,
+        path: &str,
+        modified: SystemTime,
+        encoding: PositionEncoding,
+    ) -> Option<Arc<NoteSummary>> {
+        if let Some(cached) = self.files.read().await.get(path) {
+            if cached.modified == modified && cached.encoding == encoding {
+                return Some(cached.summary.clone());
+            }
+        }
+        let content = match fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(e) => {
+                log::error!("Could not read {}: {}", path, e);
+                return None;
+            }
+        };
+        let summary = Arc::new(NoteSummary::new(&ParsedNote::parse(&content, encoding)));
+        self.files.write().await.insert(
+            path.to_string(),
+            CachedSummary {
+                modified,
+                encoding,
+                summary: summary.clone(),
+            },
+        );
+        Some(summary)
+    }
+}
+
+/// Maps each alias to the virtual path of the note declaring it. Aliases that are also the
+/// virtual path of a note are left out, since the note itself wins.
+fn aliases(notes: &[IndexedNote]) -> HashMap<String, String> {
+    let mut aliases = HashMap::new();
+    for note in notes {
+        for alias in &note.summary.aliases {
+            aliases
+                .entry(alias.clone())
+                .or_insert_with(|| note.info.virtual_path.clone());
+        }
+    }
+    for note in notes {
+        aliases.remove(&note.info.virtual_path);
+    }
+    aliases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::SqlitePool;
+
+    async fn database(notes: &[(&str, &str, &std::path::Path)]) -> db::Database {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE files (virtual_path TEXT, title TEXT, path TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (virtual_path, title, path) in notes {
+            sqlx::query("INSERT INTO files (virtual_path, title, path) VALUES (?, ?, ?)")
+                .bind(virtual_path)
+                .bind(title)
+                .bind(path.to_string_lossy())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        db::Database::from_pool(pool)
+    }
+
+    fn titles(notes: &[IndexedNote]) -> Vec<&str> {
+        notes.iter().map(|n| n.info.title.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn notes_are_kept_until_invalidated_and_open_documents_win() {
+        let dir = std::env::temp_dir().join(format!("gnosis-note-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("alpha.md");
+        std::fs::write(&path, "---\ntitle: On disk\naliases: [A]\n---\n").unwrap();
+        let db = database(&[("alpha", "Alpha", &path)]).await;
+        let documents = DocumentStore::default();
+        let index = NoteIndex::default();
+        let encoding = PositionEncoding::Utf16;
+
+        assert_eq!(
+            titles(&index.notes(&db, &documents, encoding).await),
+            ["On disk"]
+        );
+
+        // Open documents are summarized from their unsaved text.
+        let uri = Url::from_file_path(&path).unwrap();
+        documents.open(uri.clone(), "# No frontmatter\n", 1).await;
+        assert_eq!(
+            titles(&index.notes(&db, &documents, encoding).await),
+            ["Alpha"]
+        );
+        documents.close(&uri).await;
+
+        // The disk is only looked at again after an invalidation.
+        std::fs::write(&path, "---\ntitle: Saved\n---\n").unwrap();
+        assert_eq!(
+            titles(&index.notes(&db, &documents, encoding).await),
+            ["On disk"]
+        );
+        assert!(index
+            .aliases(&db, &documents, encoding)
+            .await
+            .contains_key("A"));
+        index.invalidate().await;
+        assert_eq!(
+            titles(&index.notes(&db, &documents, encoding).await),
+            ["Saved"]
+        );
+        assert!(index.aliases(&db, &documents, encoding).await.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
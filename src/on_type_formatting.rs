@@ -0,0 +1,234 @@
+// src/on_type_formatting.rs
+
+use crate::note::ParsedNote;
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+use tower_lsp::lsp_types::{Position, TextEdit};
+
+/// Matches the start of a list item: the indentation (block quote markers included), the bullet
+/// or number, and an optional task checkbox.
+fn list_item_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^(?P<indent>(?:[ \t]*>)*[ \t]*)(?:(?P<bullet>[-*+])|(?P<number>\d{1,9})(?P<delimiter>[.)]))(?:[ \t]+|$)(?P<task>\[[ xX]\](?:[ \t]+|$))?",
+        )
+        .unwrap()
+    })
+}
+
+/// Returns the edits to apply after the user typed `ch` at `position`, the position right after
+/// the typed character:
+///
+/// - on a newline following a list item, the new line continues the list with the same
+///   indentation and bullet, the next number (renumbering the rest of an ordered list) and an
+///   unchecked box for task lists;
+/// - on a newline following an empty list item, the empty item is removed instead;
+/// - on the second `[` of `[[`, the closing `]]` is inserted.
+pub fn get_on_type_edits(note: &ParsedNote, position: Position, ch: &str) -> Vec<TextEdit> {
+    let offset = note.offset(position);
+    if note.in_code(offset.saturating_sub(1)) {
+        return Vec::new();
+    }
+    match ch {
+        "\n" => continue_list(note, position),
+        "[" => close_wiki_link(note, position),
+        _ => Vec::new(),
+    }
+}
+
+fn continue_list(note: &ParsedNote, position: Position) -> Vec<TextEdit> {
+    let Some(previous_line) = position.line.checked_sub(1) else {
+        return Vec::new();
+    };
+    let Some(item) = list_item(note, previous_line) else {
+        return Vec::new();
+    };
+    let previous = note.line(previous_line as usize).unwrap_or_default();
+    let indent = &item["indent"];
+    let marker_end = item.get(0).map_or(0, |m| m.end());
+    let line_start = note.offset(Position::new(position.line, 0));
+    let current = note.line(position.line as usize).unwrap_or_default();
+    // Whitespace the editor may have inserted on the new line.
+    let current_indent = current.len() - current.trim_start().len();
+
+    let start = note.offset(Position::new(previous_line, 0));
+    // Inside a list, an empty item may not parse as one: `- foo` followed by an indented `-`
+    // reads as a heading underline.
+    let in_list = note
+        .list_items
+        .iter()
+        .any(|span| span.start < start && start < span.end);
+    if previous[marker_end..].trim().is_empty()
+        && (in_list || is_parsed_item(note, previous_line, &item))
+    {
+        // Second Enter on an empty item: drop the item, keeping the block quote markers.
+        let quote = indent.trim_end_matches([' ', '\t']);
+        let quote = if quote.is_empty() {
+            String::new()
+        } else {
+            format!("{} ", quote)
+        };
+        return vec![TextEdit {
+            range: note.range(&(start..line_start + current_indent)),
+            new_text: quote,
+        }];
+    }
+    if !is_parsed_item(note, previous_line, &item) {
+        return Vec::new();
+    }
+
+    let mut new_text = indent.to_string();
+    let next_number = match (item.name("bullet"), item.name("number")) {
+        (Some(bullet), _) => {
+            new_text.push_str(bullet.as_str());
+            None
+        }
+        (None, Some(number)) => {
+            let next = number.as_str().parse::<u64>().unwrap_or(0) + 1;
+            new_text.push_str(&format!("{}{}", next, &item["delimiter"]));
+            Some(next)
+        }
+        (None, None) => return Vec::new(),
+    };
+    new_text.push(' ');
+    if item.name("task").is_some() {
+        new_text.push_str("[ ] ");
+    }
+    let mut edits = vec![TextEdit {
+        range: note.range(&(line_start..line_start + current_indent)),
+        new_text,
+    }];
+    if let Some(next) = next_number {
+        edits.extend(renumber(note, position.line + 1, indent, next + 1));
+    }
+    edits
+}
+
+/// Renumbers the ordered items that follow `first_line` at the same nesting level, starting at
+/// `number`. Deeper lines (nested lists, continuation paragraphs) are skipped; the list ends at
+/// the first line that is less indented or is not an item of that level.
+fn renumber(note: &ParsedNote, first_line: u32, indent: &str, mut number: u64) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+    let mut line = first_line;
+    while let Some(text) = note.line(line as usize) {
+        if text.trim().is_empty() {
+            line += 1;
+            continue;
+        }
+        let text_indent = &text[..text.len() - text.trim_start().len()];
+        if text_indent.len() > indent.len() {
+            line += 1;
+            continue;
+        }
+        let Some(item) = list_item(note, line)
+            .filter(|item| &item["indent"] == indent && is_parsed_item(note, line, item))
+        else {
+            break;
+        };
+        let Some(current) = item.name("number") else {
+            break;
+        };
+        if current.as_str().parse::<u64>().ok() != Some(number) {
+            let start = note.offset(Position::new(line, 0));
+            edits.push(TextEdit {
+                range: note.range(&(start + current.start()..start + current.end())),
+                new_text: number.to_string(),
+            });
+        }
+        number += 1;
+        line += 1;
+    }
+    edits
+}
+
+/// Matches the list item marker starting `line`.
+fn list_item(note: &ParsedNote, line: u32) -> Option<Captures<'_>> {
+    list_item_regex().captures(note.line(line as usize)?)
+}
+
+/// Whether the markdown parser agrees that `item`, matched on `line`, starts a list item. An
+/// empty item right below a paragraph is for instance a setext heading underline.
+fn is_parsed_item(note: &ParsedNote, line: u32, item: &Captures) -> bool {
+    let start = note.offset(Position::new(line, 0)) + item["indent"].len();
+    note.list_items.iter().any(|span| span.start == start)
+}
+
+fn close_wiki_link(note: &ParsedNote, position: Position) -> Vec<TextEdit> {
+    let offset = note.offset(position);
+    let line_start = note.offset(Position::new(position.line, 0));
+    let before = &note.text[line_start..offset];
+    if !before.ends_with("[[") || before.ends_with("[[[") {
+        return Vec::new();
+    }
+    // Leave links that are already closed alone, e.g. when `[[` is typed before `note]]`.
+    let after = note.line(position.line as usize).unwrap_or_default();
+    let after = &after[(offset - line_start).min(after.len())..];
+    let closing = after.find("]]");
+    if closing.is_some_and(|close| after.find("[[").is_none_or(|open| close < open)) {
+        return Vec::new();
+    }
+    vec![TextEdit {
+        range: note.range(&(offset..offset)),
+        new_text: "]]".to_string(),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::PositionEncoding;
+
+    /// Applies the edits returned after typing `ch`, `position` being right after it.
+    fn type_char(text: &str, line: u32, character: u32, ch: &str) -> String {
+        let note = ParsedNote::parse(text, PositionEncoding::Utf16);
+        note.apply_edits(&get_on_type_edits(
+            &note,
+            Position::new(line, character),
+            ch,
+        ))
+    }
+
+    #[test]
+    fn newline_continues_the_list() {
+        assert_eq!(type_char("- one\n", 1, 0, "\n"), "- one\n- ");
+        assert_eq!(type_char("> * one\n", 1, 0, "\n"), "> * one\n> * ");
+        assert_eq!(
+            type_char("- [x] done\n  ", 1, 2, "\n"),
+            "- [x] done\n- [ ] "
+        );
+    }
+
+    #[test]
+    fn ordered_lists_are_renumbered_at_the_same_level() {
+        let text = "1. a\n\n2. b\n   1. x\n   2. y\n3. c\n";
+        assert_eq!(
+            type_char(text, 1, 0, "\n"),
+            "1. a\n2. \n3. b\n   1. x\n   2. y\n4. c\n"
+        );
+        let text = "1. a\n2. b\n   1. x\n\n   2. y\n3. c\n";
+        assert_eq!(
+            type_char(text, 3, 0, "\n"),
+            "1. a\n2. b\n   1. x\n   2. \n   3. y\n3. c\n"
+        );
+    }
+
+    #[test]
+    fn newline_after_an_empty_item_removes_it() {
+        assert_eq!(type_char("- one\n- \n", 2, 0, "\n"), "- one\n");
+        assert_eq!(type_char("> - one\n> - \n", 2, 0, "\n"), "> - one\n> ");
+    }
+
+    #[test]
+    fn lists_in_code_are_left_alone() {
+        let text = "```\n- a\n\n```\n";
+        assert_eq!(type_char(text, 2, 0, "\n"), text);
+    }
+
+    #[test]
+    fn wiki_links_are_closed() {
+        assert_eq!(type_char("see [[", 0, 6, "["), "see [[]]");
+        assert_eq!(type_char("see [[note]]", 0, 6, "["), "see [[note]]");
+        assert_eq!(type_char("see [[[", 0, 7, "["), "see [[[");
+    }
+}
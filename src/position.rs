@@ -0,0 +1,254 @@
+// src/position.rs
+
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range};
+
+/// The unit in which LSP positions count columns, negotiated with the client in `initialize`.
+/// Text is stored as UTF-8, so every column sent or received goes through this type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    /// The LSP default, used when the client does not announce any encoding.
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Picks an encoding among the ones offered by the client. UTF-8 is preferred since it needs
+    /// no conversion; UTF-16 is the mandatory fallback.
+    pub fn negotiate(offered: Option<&[PositionEncodingKind]>) -> Self {
+        let offered = offered.unwrap_or_default();
+        if offered.contains(&PositionEncodingKind::UTF8) {
+            PositionEncoding::Utf8
+        } else if offered.contains(&PositionEncodingKind::UTF32) {
+            PositionEncoding::Utf32
+        } else {
+            PositionEncoding::Utf16
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Number of code units `c` takes in this encoding.
+    pub fn char_len(self, c: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+
+    /// Converts a byte offset into `line` to a column.
+    pub fn column(self, line: &str, byte: usize) -> u32 {
+        let prefix = &line[..floor_char_boundary(line, byte)];
+        match self {
+            PositionEncoding::Utf8 => prefix.len() as u32,
+            PositionEncoding::Utf16 => prefix.encode_utf16().count() as u32,
+            PositionEncoding::Utf32 => prefix.chars().count() as u32,
+        }
+    }
+
+    /// Converts a column to a byte offset into `line`. Columns past the end of the line are
+    /// clamped to its length and columns inside a character snap to its start, so the result can
+    /// always be used to slice `line`.
+    pub fn byte_offset(self, line: &str, column: u32) -> usize {
+        let column = column as usize;
+        let mut units = 0;
+        for (byte, c) in line.char_indices() {
+            let len = self.char_len(c);
+            if units + len > column {
+                return byte;
+            }
+            units += len;
+        }
+        line.len()
+    }
+
+    /// Builds the range covering the bytes `start..end` of line `line_index`.
+    pub fn range(self, line_index: usize, line: &str, start: usize, end: usize) -> Range {
+        Range {
+            start: Position {
+                line: line_index as u32,
+                character: self.column(line, start),
+            },
+            end: Position {
+                line: line_index as u32,
+                character: self.column(line, end),
+            },
+        }
+    }
+}
+
+/// Rounds a byte offset down to the nearest char boundary of `s`.
+fn floor_char_boundary(s: &str, mut byte: usize) -> usize {
+    if byte >= s.len() {
+        return s.len();
+    }
+    while !s.is_char_boundary(byte) {
+        byte -= 1;
+    }
+    byte
+}
+
+/// Start offsets of the lines of a document, to convert between byte offsets into the whole
+/// text and LSP positions. Like LSP, and like the rope of open documents, lines end at `\n`,
+/// `\r\n` or a lone `\r` only.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    /// Byte offset at which each line starts.
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let line_breaks = bytes
+            .iter()
+            .enumerate()
+            .filter(|&(i, &b)| b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n')));
+        let line_starts = std::iter::once(0)
+            .chain(line_breaks.map(|(i, _)| i + 1))
+            .collect();
+        Self { line_starts }
+    }
+
+    /// Zero-based line containing the byte offset.
+    pub fn line_of(&self, byte: usize) -> usize {
+        match self.line_starts.binary_search(&byte) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// Byte offset at which `line` starts, or `None` past the last line.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line).copied()
+    }
+
+    /// Iterates over the lines of `text`, without their line ending.
+    pub fn lines<'a, 't: 'a>(&'a self, text: &'t str) -> impl Iterator<Item = &'t str> + 'a {
+        (0..self.line_starts.len()).map_while(move |line| self.line(text, line))
+    }
+
+    /// Returns line `line` of `text` without its line ending.
+    pub fn line<'t>(&self, text: &'t str, line: usize) -> Option<&'t str> {
+        let start = self.line_start(line)?;
+        let end = self.line_start(line + 1).unwrap_or(text.len());
+        Some(text[start..end].trim_end_matches(['\n', '\r']))
+    }
+
+    pub fn position(&self, text: &str, byte: usize, encoding: PositionEncoding) -> Position {
+        let byte = byte.min(text.len());
+        let line = self.line_of(byte);
+        let line_start = self.line_starts[line];
+        Position {
+            line: line as u32,
+            character: encoding.column(&text[line_start..], byte - line_start),
+        }
+    }
+
+    pub fn range(&self, text: &str, start: usize, end: usize, encoding: PositionEncoding) -> Range {
+        Range {
+            start: self.position(text, start, encoding),
+            end: self.position(text, end, encoding),
+        }
+    }
+
+    /// Converts a position to a byte offset into `text`. Positions past the end of a line are
+    /// clamped to the end of that line, positions past the last line to the end of the text.
+    pub fn offset(&self, text: &str, pos: Position, encoding: PositionEncoding) -> usize {
+        match self.line(text, pos.line as usize) {
+            Some(line) => {
+                self.line_starts[pos.line as usize] + encoding.byte_offset(line, pos.character)
+            }
+            None => text.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_the_preferred_encoding() {
+        let offered = [PositionEncodingKind::UTF16, PositionEncodingKind::UTF8];
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&offered)),
+            PositionEncoding::Utf8
+        );
+        let offered = [PositionEncodingKind::UTF32];
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&offered)),
+            PositionEncoding::Utf32
+        );
+        assert_eq!(PositionEncoding::negotiate(None), PositionEncoding::Utf16);
+    }
+
+    #[test]
+    fn converts_multi_byte_columns() {
+        // `é` is 2 bytes and 1 UTF-16 unit, `😀` 4 bytes and 2 UTF-16 units.
+        let line = "é😀x";
+        let x = "é😀".len();
+        assert_eq!(PositionEncoding::Utf8.column(line, x), 6);
+        assert_eq!(PositionEncoding::Utf16.column(line, x), 3);
+        assert_eq!(PositionEncoding::Utf32.column(line, x), 2);
+        assert_eq!(PositionEncoding::Utf8.byte_offset(line, 6), x);
+        assert_eq!(PositionEncoding::Utf16.byte_offset(line, 3), x);
+        assert_eq!(PositionEncoding::Utf32.byte_offset(line, 2), x);
+    }
+
+    #[test]
+    fn snaps_columns_inside_a_character() {
+        let line = "a😀b";
+        // Column 2 is between the two UTF-16 units of the emoji.
+        assert_eq!(PositionEncoding::Utf16.byte_offset(line, 2), 1);
+        // Byte 3 is inside the emoji.
+        assert_eq!(PositionEncoding::Utf16.column(line, 3), 1);
+        assert_eq!(PositionEncoding::Utf8.byte_offset(line, 3), 1);
+    }
+
+    #[test]
+    fn clamps_columns_past_the_end_of_the_line() {
+        assert_eq!(PositionEncoding::Utf16.byte_offset("abc", 10), 3);
+        assert_eq!(PositionEncoding::Utf16.column("abc", 10), 3);
+    }
+
+    #[test]
+    fn splits_lines_at_lsp_line_breaks_only() {
+        let text = "a\r\nb\rc\u{2028}d\ne";
+        let index = LineIndex::new(text);
+        let lines: Vec<&str> = (0..4).filter_map(|i| index.line(text, i)).collect();
+        assert_eq!(lines, ["a", "b", "c\u{2028}d", "e"]);
+        assert_eq!(index.line(text, 4), None);
+    }
+
+    #[test]
+    fn converts_offsets_to_positions_and_back() {
+        let text = "# Tëst\r\n😀 [[note]]\n";
+        let index = LineIndex::new(text);
+        let link = text.find("[[").unwrap();
+        let pos = index.position(text, link, PositionEncoding::Utf16);
+        assert_eq!(pos, Position::new(1, 3));
+        assert_eq!(index.offset(text, pos, PositionEncoding::Utf16), link);
+        // The end of the first line is before its `\r\n`.
+        assert_eq!(
+            index.offset(text, Position::new(0, 99), PositionEncoding::Utf16),
+            "# Tëst".len()
+        );
+        assert_eq!(
+            index.offset(text, Position::new(5, 0), PositionEncoding::Utf16),
+            text.len()
+        );
+        assert_eq!(
+            index.position(text, text.len(), PositionEncoding::Utf16),
+            Position::new(2, 0)
+        );
+    }
+}
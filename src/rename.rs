@@ -0,0 +1,428 @@
+// src/rename.rs
+
+use crate::code_actions;
+use crate::db::FileInfo;
+use crate::link_references::HybridIndex;
+use crate::note::ParsedNote;
+use crate::position::PositionEncoding;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tower_lsp::lsp_types::{
+    AnnotatedTextEdit, ChangeAnnotation, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, RenameFile, ResourceOp, TextDocumentEdit, TextEdit,
+    WorkspaceEdit,
+};
+use url::Url;
+
+/// Describes how the wiki-links pointing at a renamed note have to change.
+pub struct LinkRewrite<'a> {
+    pub old_virtual_path: &'a str,
+    pub new_virtual_path: &'a str,
+    /// `(old_title, new_title)`: aliases equal to the old title are replaced by the new one.
+    pub retitle: Option<(&'a str, &'a str)>,
+}
+
+/// Splits the name typed by the user into a virtual path and an optional new title.
+/// Accepted forms are `new/path` and `new/path|New Title`.
+pub fn parse_new_name(new_name: &str) -> (String, Option<String>) {
+    let mut parts = new_name.splitn(2, '|');
+    let virtual_path = parts.next().unwrap_or("").trim().to_string();
+    let title = parts
+        .next()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+    (virtual_path, title)
+}
+
+/// Checks the virtual path a note is renamed to. Returns why it is rejected: it is empty, it
+/// cannot be written inside a wiki-link, it would move the note out of the workspace, or another
+/// note already has it.
+pub fn check_new_virtual_path(
+    new_virtual_path: &str,
+    old_virtual_path: &str,
+    infos: &[FileInfo],
+) -> Result<(), String> {
+    if new_virtual_path.is_empty() {
+        return Err("The new virtual path must not be empty".to_string());
+    }
+    if ["[[", "]]", "#"]
+        .iter()
+        .any(|s| new_virtual_path.contains(s))
+    {
+        return Err(format!(
+            "`{}` cannot be written in a wiki-link: `[[`, `]]` and `#` are not allowed",
+            new_virtual_path
+        ));
+    }
+    if !code_actions::stays_in_workspace(new_virtual_path) {
+        return Err(format!(
+            "`{}` points outside of the workspace",
+            new_virtual_path
+        ));
+    }
+    let same = |a: &str, b: &str| a.trim_matches('/') == b.trim_matches('/');
+    if !same(new_virtual_path, old_virtual_path)
+        && infos
+            .iter()
+            .any(|f| same(&f.virtual_path, new_virtual_path))
+    {
+        return Err(format!(
+            "A note named `{}` already exists",
+            new_virtual_path
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the edits rewriting every wiki-link of `note` that points at the renamed note.
+/// Links inside code are left alone.
+pub fn rewrite_links(note: &ParsedNote, rewrite: &LinkRewrite<'_>) -> Vec<TextEdit> {
+    note.wiki_links
+        .iter()
+        .filter(|link| link.value.virtual_path == rewrite.old_virtual_path)
+        .map(|spanned| {
+            let link = &spanned.value;
+            let alias = match (&link.alias, rewrite.retitle) {
+                (Some(alias), Some((old_title, new_title))) if alias == old_title => {
+                    Some(new_title.to_string())
+                }
+                (alias, _) => alias.clone(),
+            };
+            let target = match &link.anchor {
+                Some(anchor) => format!("{}#{}", rewrite.new_virtual_path, anchor),
+                None => rewrite.new_virtual_path.to_string(),
+            };
+            let new_text = match alias {
+                Some(alias) => format!("[[{}|{}]]", target, alias),
+                None => format!("[[{}]]", target),
+            };
+            TextEdit {
+                range: spanned.range,
+                new_text,
+            }
+        })
+        .collect()
+}
+
+/// Collects the link rewrites for every file of the workspace.
+/// Open documents are taken from `open_notes` (they may have unsaved links); all other files
+/// are found through ripgrep and read from disk.
+pub async fn link_rewrite_edits(
+    index: &HybridIndex,
+    open_notes: &HashMap<Url, Arc<ParsedNote>>,
+    rewrite: &LinkRewrite<'_>,
+    encoding: PositionEncoding,
+) -> HashMap<Url, Vec<TextEdit>> {
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+    for (uri, note) in open_notes {
+        let edits = rewrite_links(note, rewrite);
+        if !edits.is_empty() {
+            changes.insert(uri.clone(), edits);
+        }
+    }
+
+    let files = match index.files_with_references(rewrite.old_virtual_path).await {
+        Ok(files) => files,
+        Err(e) => {
+            log::error!(
+                "Error searching references to {}: {}",
+                rewrite.old_virtual_path,
+                e
+            );
+            return changes;
+        }
+    };
+    for file in files {
+        let uri = match Url::from_file_path(&file) {
+            Ok(u) => u,
+            Err(_) => {
+                log::error!("Could not convert local path {} to URI", file.display());
+                continue;
+            }
+        };
+        if open_notes.contains_key(&uri) {
+            continue;
+        }
+        match fs::read_to_string(&file).await {
+            Ok(content) => {
+                let edits = rewrite_links(&ParsedNote::parse(&content, encoding), rewrite);
+                if !edits.is_empty() {
+                    changes.insert(uri, edits);
+                }
+            }
+            Err(e) => log::error!("Could not read {}: {}", file.display(), e),
+        }
+    }
+    changes
+}
+
+/// Builds the WorkspaceEdit for a rename. When `file_rename` is given, the edit is expressed as
+/// document changes so the file operation runs after the text edits of the old file.
+pub fn build_workspace_edit(
+    changes: HashMap<Url, Vec<TextEdit>>,
+    file_rename: Option<(Url, Url)>,
+) -> WorkspaceEdit {
+    let Some((old_uri, new_uri)) = file_rename else {
+        return WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        };
+    };
+
+    let mut operations: Vec<DocumentChangeOperation> = changes
+        .into_iter()
+        .map(|(uri, edits)| {
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            })
+        })
+        .collect();
+    operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(
+        RenameFile {
+            old_uri,
+            new_uri,
+            options: None,
+            annotation_id: None,
+        },
+    )));
+
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..Default::default()
+    }
+}
+
+/// Builds a WorkspaceEdit whose text edits all carry `annotation`, so that clients supporting
+/// change annotations can group them and ask for confirmation before applying them.
+pub fn build_annotated_edit(
+    changes: HashMap<Url, Vec<TextEdit>>,
+    annotation: ChangeAnnotation,
+) -> WorkspaceEdit {
+    let annotation_id = "rename".to_string();
+    let edits = changes
+        .into_iter()
+        .map(|(uri, edits)| TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits: edits
+                .into_iter()
+                .map(|text_edit| {
+                    OneOf::Right(AnnotatedTextEdit {
+                        text_edit,
+                        annotation_id: annotation_id.clone(),
+                    })
+                })
+                .collect(),
+        })
+        .collect();
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(edits)),
+        change_annotations: Some(HashMap::from([(annotation_id, annotation)])),
+        ..Default::default()
+    }
+}
+
+/// Computes where the file of a note should live once its virtual path changes.
+/// If the local path mirrors the virtual path (e.g. `<root>/projects/alpha.md` for
+/// `/projects/alpha`), the same layout is kept for the new virtual path; otherwise the note stays
+/// in its folder and only its file name changes.
+pub fn renamed_note_path(info: &FileInfo, new_virtual_path: &str) -> PathBuf {
+    let old_path = Path::new(&info.path);
+    let extension = old_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("md");
+    let old_rel = format!(
+        "{}.{}",
+        info.virtual_path.trim_start_matches('/'),
+        extension
+    );
+    let new_rel = format!("{}.{}", new_virtual_path.trim_start_matches('/'), extension);
+    if let Some(prefix) = info.path.strip_suffix(&old_rel) {
+        return PathBuf::from(format!("{}{}", prefix, new_rel));
+    }
+    let file_name = new_rel.rsplit('/').next().unwrap_or(&new_rel);
+    old_path.with_file_name(file_name)
+}
+
+/// A rename whose new file location the client has not reported yet. The record of the note is
+/// updated once `workspace/didRenameFiles` confirms the move.
+pub struct PendingRename {
+    pub new_path: PathBuf,
+    pub new_virtual_path: String,
+    pub new_title: Option<String>,
+}
+
+/// A note affected by a file or folder move.
+pub struct MovedNote {
+    pub info: FileInfo,
+    pub new_path: PathBuf,
+    pub new_virtual_path: String,
+}
+
+/// Maps file operations (`(old_path, new_path)` pairs) onto the notes they move. Renaming a
+/// folder moves every note below it.
+pub fn moved_notes(infos: &[FileInfo], renames: &[(PathBuf, PathBuf)]) -> Vec<MovedNote> {
+    let mut moved = Vec::new();
+    for info in infos {
+        let note_path = Path::new(&info.path);
+        for (old_path, new_path) in renames {
+            let Ok(rest) = note_path.strip_prefix(old_path) else {
+                continue;
+            };
+            let new_note_path = if rest.as_os_str().is_empty() {
+                new_path.clone()
+            } else {
+                new_path.join(rest)
+            };
+            moved.push(MovedNote {
+                new_virtual_path: moved_virtual_path(info, &new_note_path),
+                info: info.clone(),
+                new_path: new_note_path,
+            });
+            break;
+        }
+    }
+    moved
+}
+
+/// Inverse of [`renamed_note_path`]: derives the virtual path of a note from its new location.
+/// When the old local path mirrors the virtual path, the new path is interpreted with the same
+/// layout; otherwise only the last segment of the virtual path follows the new file name.
+pub fn moved_virtual_path(info: &FileInfo, new_path: &Path) -> String {
+    let new_path_str = new_path.to_string_lossy();
+    let old_path = Path::new(&info.path);
+    let leading_slash = if info.virtual_path.starts_with('/') {
+        "/"
+    } else {
+        ""
+    };
+    if let Some(extension) = old_path.extension().and_then(|e| e.to_str()) {
+        let old_rel = format!(
+            "{}.{}",
+            info.virtual_path.trim_start_matches('/'),
+            extension
+        );
+        if let Some(prefix) = info.path.strip_suffix(&old_rel) {
+            if let Some(new_rel) = new_path_str.strip_prefix(prefix) {
+                let new_rel = new_rel
+                    .strip_suffix(&format!(".{}", extension))
+                    .unwrap_or(new_rel);
+                return format!("{}{}", leading_slash, new_rel);
+            }
+        }
+    }
+
+    let stem = new_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match info.virtual_path.rsplit_once('/') {
+        Some((parent, _)) => format!("{}/{}", parent, stem),
+        None => stem,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(virtual_path: &str, path: &str) -> FileInfo {
+        FileInfo {
+            virtual_path: virtual_path.to_string(),
+            title: String::new(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn new_names_may_carry_a_title() {
+        assert_eq!(
+            parse_new_name(" new/path | New Title "),
+            ("new/path".to_string(), Some("New Title".to_string()))
+        );
+        assert_eq!(parse_new_name("new/path|"), ("new/path".to_string(), None));
+    }
+
+    #[test]
+    fn new_virtual_paths_are_checked() {
+        let infos = [info("a", "/vault/a.md"), info("/b", "/vault/b.md")];
+        let check = |new: &str| check_new_virtual_path(new, "a", &infos);
+        assert!(check("x/y").is_ok());
+        // Keeping the virtual path only changes the title.
+        assert!(check("a").is_ok());
+        assert!(check("").is_err());
+        assert!(check("../../tmp/x").is_err());
+        assert!(check("notes/../../x").is_err());
+        assert!(check("x]]y").is_err());
+        assert!(check("x[[y").is_err());
+        assert!(check("x#y").is_err());
+        assert!(check("b").is_err());
+        assert!(check("/b").is_err());
+    }
+
+    #[test]
+    fn mirrored_layouts_keep_mirroring_the_virtual_path() {
+        let note = info("/projects/alpha", "/vault/projects/alpha.md");
+        let new_path = renamed_note_path(&note, "/archive/2024/alpha");
+        assert_eq!(new_path, PathBuf::from("/vault/archive/2024/alpha.md"));
+        assert_eq!(moved_virtual_path(&note, &new_path), "/archive/2024/alpha");
+    }
+
+    #[test]
+    fn other_layouts_only_follow_the_file_name() {
+        let note = info("notes/alpha", "/vault/inbox/2024-alpha.md");
+        let new_path = renamed_note_path(&note, "projects/beta");
+        assert_eq!(new_path, PathBuf::from("/vault/inbox/beta.md"));
+        assert_eq!(
+            moved_virtual_path(&note, Path::new("/vault/elsewhere/gamma.md")),
+            "notes/gamma"
+        );
+    }
+
+    #[test]
+    fn folder_moves_move_every_note_below_the_folder() {
+        let infos = [
+            info("projects/alpha", "/vault/projects/alpha.md"),
+            info("projects/sub/beta", "/vault/projects/sub/beta.md"),
+            info("projects-old/gamma", "/vault/projects-old/gamma.md"),
+        ];
+        let renames = [(
+            PathBuf::from("/vault/projects"),
+            PathBuf::from("/vault/archive"),
+        )];
+        let moved: Vec<(String, PathBuf)> = moved_notes(&infos, &renames)
+            .into_iter()
+            .map(|m| (m.new_virtual_path, m.new_path))
+            .collect();
+        assert_eq!(
+            moved,
+            vec![
+                (
+                    "archive/alpha".to_string(),
+                    PathBuf::from("/vault/archive/alpha.md")
+                ),
+                (
+                    "archive/sub/beta".to_string(),
+                    PathBuf::from("/vault/archive/sub/beta.md")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_moves_move_the_note() {
+        let infos = [info("alpha", "/vault/alpha.md")];
+        let renames = [(
+            PathBuf::from("/vault/alpha.md"),
+            PathBuf::from("/vault/done/alpha.md"),
+        )];
+        let moved = moved_notes(&infos, &renames);
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].new_virtual_path, "done/alpha");
+        assert_eq!(moved[0].new_path, PathBuf::from("/vault/done/alpha.md"));
+    }
+}
@@ -0,0 +1,105 @@
+// src/selection_range.rs
+
+use crate::note::ParsedNote;
+use std::ops;
+use tower_lsp::lsp_types::{Position, SelectionRange};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Returns the selection range chain for a cursor position, from the innermost element to the
+/// whole document: the part of a wiki-link (alias, target or anchor), the wiki-link, the inline
+/// spans (emphasis, code, links), the sentence, the paragraph, heading or list item, the
+/// enclosing block quotes, the heading sections and finally the document.
+pub fn get_selection_range(note: &ParsedNote, position: Position) -> SelectionRange {
+    let offset = note.offset(position);
+    let contains = |span: &ops::Range<usize>| span.start <= offset && offset <= span.end;
+
+    let mut spans: Vec<ops::Range<usize>> = Vec::new();
+    if let Some(link) = note.wiki_links.iter().find(|l| contains(&l.span)) {
+        let parts = note.wiki_link_parts(&link.span);
+        let part = [Some(parts.target), parts.anchor, parts.alias]
+            .into_iter()
+            .flatten()
+            .find(|p| contains(p));
+        spans.extend(part);
+        spans.push(link.span.clone());
+    }
+    let inline = note
+        .emphasis
+        .iter()
+        .chain(&note.strong)
+        .chain(&note.strikethrough)
+        .chain(&note.code_spans)
+        .chain(note.markdown_links.iter().map(|l| &l.span));
+    spans.extend(inline.filter(|s| contains(s)).cloned());
+
+    let blocks = note
+        .paragraphs
+        .iter()
+        .chain(note.headings.iter().map(|h| &h.span))
+        .chain(&note.list_items)
+        .chain(&note.block_quotes)
+        .chain(&note.code_blocks)
+        .chain(note.frontmatter.iter().map(|f| &f.span));
+    let blocks: Vec<ops::Range<usize>> = blocks
+        .filter(|s| contains(s))
+        .map(|s| note.trimmed(s.clone()))
+        .collect();
+    // Sentences are looked for in the innermost paragraph, heading or (tight) list item.
+    let text_block = note
+        .paragraphs
+        .iter()
+        .chain(note.headings.iter().map(|h| &h.span))
+        .chain(&note.list_items)
+        .filter(|s| contains(s))
+        .min_by_key(|s| s.len());
+    if let Some(block) = text_block {
+        spans.extend(sentence_at(note, block.clone(), offset));
+    }
+    spans.extend(blocks);
+    spans.extend(
+        (0..note.headings.len())
+            .map(|i| note.section(i))
+            .filter(|s| contains(s)),
+    );
+    spans.push(0..note.text.len());
+
+    // Innermost first; a span that does not contain the previous one cannot be an expansion.
+    spans.sort_by_key(|s| (s.len(), std::cmp::Reverse(s.start)));
+    let mut chain: Vec<ops::Range<usize>> = Vec::new();
+    for span in spans {
+        let nests = chain
+            .last()
+            .is_none_or(|last| span.start <= last.start && last.end <= span.end && span != *last);
+        if nests {
+            chain.push(span);
+        }
+    }
+
+    let mut selection: Option<SelectionRange> = None;
+    for span in chain.iter().rev() {
+        selection = Some(SelectionRange {
+            range: note.range(span),
+            parent: selection.map(Box::new),
+        });
+    }
+    selection.unwrap_or(SelectionRange {
+        range: note.range(&(offset..offset)),
+        parent: None,
+    })
+}
+
+/// The sentence of `block` containing `offset`, without surrounding whitespace.
+fn sentence_at(
+    note: &ParsedNote,
+    block: ops::Range<usize>,
+    offset: usize,
+) -> Option<ops::Range<usize>> {
+    // Line breaks inside a paragraph are soft wraps, not sentence boundaries.
+    let flattened = note.text[block.clone()].replace(['\n', '\r'], " ");
+    flattened
+        .split_sentence_bound_indices()
+        .map(|(start, sentence)| {
+            note.trimmed(block.start + start..block.start + start + sentence.len())
+        })
+        .find(|s| s.start <= offset && offset <= s.end)
+}
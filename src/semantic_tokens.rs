@@ -0,0 +1,343 @@
+// src/semantic_tokens.rs
+
+use crate::note::ParsedNote;
+use std::collections::{HashMap, HashSet};
+use std::ops;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::RwLock;
+use tower_lsp::lsp_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensDelta,
+    SemanticTokensEdit, SemanticTokensFullDeltaResult, SemanticTokensLegend,
+};
+use url::Url;
+
+/// Token types, in legend order. Standard types are used so that themes color the tokens
+/// without extra configuration.
+const TOKEN_TYPES: [SemanticTokenType; 6] = [
+    // `[[`, `|` and `]]`.
+    SemanticTokenType::OPERATOR,
+    // The virtual path of a wiki-link.
+    SemanticTokenType::NAMESPACE,
+    // The alias of a wiki-link.
+    SemanticTokenType::STRING,
+    // A `#Heading` anchor.
+    SemanticTokenType::PROPERTY,
+    // A `#tag`.
+    SemanticTokenType::DECORATOR,
+    // A `^block-id`, declared in the note or used as anchor.
+    SemanticTokenType::ENUM_MEMBER,
+];
+const BRACKET: u32 = 0;
+const TARGET: u32 = 1;
+const ALIAS: u32 = 2;
+const ANCHOR: u32 = 3;
+const TAG: u32 = 4;
+const BLOCK_ID: u32 = 5;
+
+/// Modifier set on every token of a wiki-link whose target is not in the `files` table.
+pub const UNRESOLVED: SemanticTokenModifier = SemanticTokenModifier::new("unresolved");
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: vec![UNRESOLVED],
+    }
+}
+
+/// A token before relative encoding.
+struct Token {
+    span: ops::Range<usize>,
+    token_type: u32,
+    modifiers: u32,
+}
+
+/// Returns the semantic tokens of a note: the parts of its wiki-links, its tags and its block
+/// ids. Links whose virtual path is missing from `known_notes` carry the [`UNRESOLVED`] modifier;
+/// pass `None` when the database is not available and nothing can be resolved.
+pub fn get_semantic_tokens(
+    note: &ParsedNote,
+    known_notes: Option<&HashSet<String>>,
+) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    for link in &note.wiki_links {
+        let unresolved = known_notes.is_some_and(|known| !known.contains(&link.value.virtual_path));
+        let modifiers = if unresolved { 1 } else { 0 };
+        wiki_link_tokens(note, link.span.clone(), modifiers, &mut tokens);
+    }
+    for tag in &note.tags {
+        tokens.push(Token {
+            span: tag.span.clone(),
+            token_type: TAG,
+            modifiers: 0,
+        });
+    }
+    for block in &note.block_ids {
+        tokens.push(Token {
+            span: block.span.clone(),
+            token_type: BLOCK_ID,
+            modifiers: 0,
+        });
+    }
+    tokens.sort_by_key(|t| t.span.start);
+    encode(note, &tokens)
+}
+
+/// Splits `[[path#anchor|alias]]` into bracket, target, anchor and alias tokens.
+fn wiki_link_tokens(
+    note: &ParsedNote,
+    span: ops::Range<usize>,
+    modifiers: u32,
+    tokens: &mut Vec<Token>,
+) {
+    let mut push = |span: ops::Range<usize>, token_type: u32| {
+        if !span.is_empty() {
+            tokens.push(Token {
+                span,
+                token_type,
+                modifiers,
+            });
+        }
+    };
+    let parts = note.wiki_link_parts(&span);
+    push(span.start..span.start + 2, BRACKET);
+    push(parts.target, TARGET);
+    if let Some(anchor) = parts.anchor {
+        let anchor_type = if note.text[anchor.start + 1..].trim_start().starts_with('^') {
+            BLOCK_ID
+        } else {
+            ANCHOR
+        };
+        push(anchor, anchor_type);
+    }
+    if let Some(pipe) = parts.pipe {
+        push(pipe..pipe + 1, BRACKET);
+    }
+    if let Some(alias) = parts.alias {
+        push(alias, ALIAS);
+    }
+    push(span.end - 2..span.end, BRACKET);
+}
+
+/// Encodes tokens relative to each other, as the protocol requires. Overlapping tokens are
+/// dropped since clients cannot represent them.
+fn encode(note: &ParsedNote, tokens: &[Token]) -> Vec<SemanticToken> {
+    let mut data = Vec::with_capacity(tokens.len());
+    let (mut prev_line, mut prev_start, mut prev_end) = (0, 0, 0);
+    for token in tokens {
+        if token.span.start < prev_end {
+            continue;
+        }
+        let range = note.range(&token.span);
+        if range.start.line != range.end.line {
+            continue;
+        }
+        let delta_line = range.start.line - prev_line;
+        let delta_start = if delta_line == 0 {
+            range.start.character - prev_start
+        } else {
+            range.start.character
+        };
+        data.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length: range.end.character - range.start.character,
+            token_type: token.token_type,
+            token_modifiers_bitset: token.modifiers,
+        });
+        prev_line = range.start.line;
+        prev_start = range.start.character;
+        prev_end = token.span.end;
+    }
+    data
+}
+
+/// The tokens last sent for each document, so that later requests can be answered with a delta.
+#[derive(Default)]
+pub struct TokenCache {
+    next_id: AtomicU64,
+    sent: RwLock<HashMap<Url, (String, Vec<SemanticToken>)>>,
+}
+
+impl TokenCache {
+    /// Remembers the tokens of a full response and returns them with a new result id.
+    pub async fn full(&self, uri: &Url, data: Vec<SemanticToken>) -> SemanticTokens {
+        let result_id = self.store(uri, data.clone()).await;
+        SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        }
+    }
+
+    /// Answers a delta request. When the previous result is no longer known the full tokens are
+    /// returned instead.
+    pub async fn delta(
+        &self,
+        uri: &Url,
+        previous_result_id: &str,
+        data: Vec<SemanticToken>,
+    ) -> SemanticTokensFullDeltaResult {
+        let previous = self
+            .sent
+            .read()
+            .await
+            .get(uri)
+            .filter(|(id, _)| id == previous_result_id)
+            .map(|(_, tokens)| tokens.clone());
+        let Some(previous) = previous else {
+            return SemanticTokensFullDeltaResult::Tokens(self.full(uri, data).await);
+        };
+        let edits = diff(&previous, &data);
+        let result_id = self.store(uri, data).await;
+        SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+            result_id: Some(result_id),
+            edits,
+        })
+    }
+
+    pub async fn remove(&self, uri: &Url) {
+        self.sent.write().await.remove(uri);
+    }
+
+    async fn store(&self, uri: &Url, data: Vec<SemanticToken>) -> String {
+        let result_id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        self.sent
+            .write()
+            .await
+            .insert(uri.clone(), (result_id.clone(), data));
+        result_id
+    }
+}
+
+/// Describes the change from `old` to `new` as a single edit replacing everything between their
+/// common prefix and suffix. Offsets count integers, and each token is five of them.
+fn diff(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::PositionEncoding;
+
+    fn token(delta_line: u32, delta_start: u32, length: u32, token_type: u32) -> SemanticToken {
+        SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    #[test]
+    fn tokens_are_relative_and_use_the_negotiated_encoding() {
+        let note = ParsedNote::parse("é [[a|b]] #t\n😀 ^id\n", PositionEncoding::Utf16);
+        assert_eq!(
+            get_semantic_tokens(&note, None),
+            vec![
+                token(0, 2, 2, BRACKET),
+                token(0, 2, 1, TARGET),
+                token(0, 1, 1, BRACKET),
+                token(0, 1, 1, ALIAS),
+                token(0, 1, 2, BRACKET),
+                token(0, 3, 2, TAG),
+                token(1, 3, 3, BLOCK_ID),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_targets_are_unresolved() {
+        let note = ParsedNote::parse("[[known]] [[missing#Part]]", PositionEncoding::Utf16);
+        let known = HashSet::from(["known".to_string()]);
+        let modifiers: Vec<(u32, u32)> = get_semantic_tokens(&note, Some(&known))
+            .iter()
+            .map(|t| (t.token_type, t.token_modifiers_bitset))
+            .collect();
+        assert_eq!(
+            modifiers,
+            vec![
+                (BRACKET, 0),
+                (TARGET, 0),
+                (BRACKET, 0),
+                (BRACKET, 1),
+                (TARGET, 1),
+                (ANCHOR, 1),
+                (BRACKET, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_replaces_the_tokens_between_common_prefix_and_suffix() {
+        let a = token(0, 0, 2, BRACKET);
+        let b = token(0, 2, 3, TARGET);
+        let c = token(1, 0, 4, TAG);
+        let d = token(0, 5, 1, ALIAS);
+        assert_eq!(
+            diff(&[a, b, c], &[a, d, d, c]),
+            vec![SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![d, d]),
+            }]
+        );
+        assert_eq!(
+            diff(&[a, b, c], &[a, c]),
+            vec![SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![]),
+            }]
+        );
+        assert_eq!(
+            diff(&[a, b], &[a, b, c]),
+            vec![SemanticTokensEdit {
+                start: 10,
+                delete_count: 0,
+                data: Some(vec![c]),
+            }]
+        );
+        assert!(diff(&[a, b], &[a, b]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn deltas_need_the_last_result_id() {
+        let cache = TokenCache::default();
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let a = token(0, 0, 2, BRACKET);
+        let b = token(0, 2, 3, TARGET);
+        let first = cache.full(&uri, vec![a]).await;
+        let id = first.result_id.unwrap();
+
+        let SemanticTokensFullDeltaResult::TokensDelta(delta) =
+            cache.delta(&uri, &id, vec![a, b]).await
+        else {
+            panic!("expected a delta");
+        };
+        assert_eq!(delta.edits.len(), 1);
+        assert_ne!(delta.result_id.as_deref(), Some(id.as_str()));
+
+        // The first result id was superseded by the delta.
+        assert!(matches!(
+            cache.delta(&uri, &id, vec![a]).await,
+            SemanticTokensFullDeltaResult::Tokens(_)
+        ));
+    }
+}
@@ -4,25 +4,60 @@ use crate::goto_definition;
 use crate::hover_preview;
 use crate::link_references;
 use crate::link_references::HybridIndex;
+use crate::note::{NoteTag, ParsedNote, Spanned};
+use crate::position::PositionEncoding;
+use crate::rename;
+use crate::wiki_links::WikiLink;
 use async_trait::async_trait;
 use log::info;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Mutex;
+use tokio::sync::RwLock;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
+use crate::code_actions;
+use crate::completion;
+use crate::config::Config;
 use crate::db;
+use crate::diagnostics;
+use crate::document_highlight;
+use crate::document_links;
+use crate::document_store::DocumentStore;
 use crate::document_symbols;
+use crate::folding_ranges;
+use crate::formatting;
+use crate::note_index;
+use crate::on_type_formatting;
+use crate::selection_range;
+use crate::semantic_tokens;
+use crate::tags;
 use crate::workspace_symbols; // <-- Import the workspace symbols module
 
+/// Client command listing locations, run by the backlinks code lens with the note's URI, the
+/// lens position and the backlinks. VS Code ships it; other clients commonly map it.
+const SHOW_REFERENCES: &str = "editor.action.showReferences";
+
 pub struct Backend {
     pub client: Client,
     pub db: Arc<db::Database>,
-    // A simple document store to cache text for open documents.
-    pub documents: Mutex<HashMap<Url, String>>,
+    /// Rope-backed store of the open documents, kept in sync incrementally.
+    pub documents: DocumentStore,
     pub ref_index: Arc<link_references::HybridIndex>,
+    /// Workspace settings sent by the client.
+    pub config: RwLock<Config>,
+    /// Capabilities announced by the client in `initialize`.
+    pub client_capabilities: RwLock<ClientCapabilities>,
+    /// Column unit negotiated with the client.
+    pub position_encoding: RwLock<PositionEncoding>,
+    /// Semantic tokens last sent per document, for delta requests.
+    pub semantic_tokens: semantic_tokens::TokenCache,
+    /// Titles, aliases and tags of the notes of the vault.
+    pub note_index: note_index::NoteIndex,
+    /// Note renames waiting for the client to move their file, by old local path.
+    pub pending_renames: RwLock<HashMap<String, rename::PendingRename>>,
 }
 
 impl Backend {
@@ -34,8 +69,419 @@ impl Backend {
         Self {
             client,
             db,
-            documents: Mutex::new(HashMap::new()),
+            documents: DocumentStore::default(),
             ref_index,
+            config: RwLock::new(Config::default()),
+            client_capabilities: RwLock::new(ClientCapabilities::default()),
+            position_encoding: RwLock::new(PositionEncoding::default()),
+            semantic_tokens: semantic_tokens::TokenCache::default(),
+            note_index: note_index::NoteIndex::default(),
+            pending_renames: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl Backend {
+    async fn encoding(&self) -> PositionEncoding {
+        *self.position_encoding.read().await
+    }
+
+    /// Returns the parsed form of an open document.
+    async fn note(&self, uri: &Url) -> Option<Arc<ParsedNote>> {
+        self.documents.note(uri, self.encoding().await).await
+    }
+
+    /// Returns the wiki-link under the cursor in an open document.
+    async fn wiki_link_at(&self, uri: &Url, pos: Position) -> Option<Spanned<WikiLink>> {
+        self.note(uri).await?.wiki_link_at(pos).cloned()
+    }
+
+    /// Every tag of the vault, open documents included with their unsaved text.
+    async fn tag_occurrences(&self) -> Vec<tags::TagOccurrence> {
+        tags::occurrences(&self.indexed_notes().await)
+    }
+
+    /// Every note of the `files` table with its frontmatter title, aliases and tags, open
+    /// documents included with their unsaved text.
+    async fn indexed_notes(&self) -> Vec<note_index::IndexedNote> {
+        self.note_index
+            .notes(self.db.as_ref(), &self.documents, self.encoding().await)
+            .await
+    }
+
+    /// The frontmatter aliases of the vault, mapped to the virtual path of their note.
+    async fn aliases(&self) -> Arc<HashMap<String, String>> {
+        self.note_index
+            .aliases(self.db.as_ref(), &self.documents, self.encoding().await)
+            .await
+    }
+
+    /// Resolves `[[Alias]]` links to the note declaring the alias. Links naming a virtual path
+    /// are returned unchanged.
+    async fn resolve_alias(&self, mut link: WikiLink) -> WikiLink {
+        if let Some(virtual_path) = self.aliases().await.get(&link.virtual_path) {
+            link.virtual_path = virtual_path.clone();
+        }
+        link
+    }
+
+    /// Whether the client can apply the given file operation inside a WorkspaceEdit.
+    async fn supports_resource_operation(&self, kind: ResourceOperationKind) -> bool {
+        let caps = self.client_capabilities.read().await;
+        caps.workspace
+            .as_ref()
+            .and_then(|w| w.workspace_edit.as_ref())
+            .map(|e| {
+                e.document_changes == Some(true)
+                    && e.resource_operations
+                        .as_ref()
+                        .is_some_and(|ops| ops.contains(&kind))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Whether the client accepts text edits annotated with a change annotation.
+    async fn supports_change_annotations(&self) -> bool {
+        let caps = self.client_capabilities.read().await;
+        caps.workspace
+            .as_ref()
+            .and_then(|w| w.workspace_edit.as_ref())
+            .is_some_and(|e| {
+                e.document_changes == Some(true) && e.change_annotation_support.is_some()
+            })
+    }
+
+    /// Returns the tag under the cursor, if any.
+    async fn tag_at(&self, uri: &Url, pos: Position) -> Option<Spanned<NoteTag>> {
+        self.note(uri).await?.tag_at(pos).cloned()
+    }
+
+    /// Renames a tag and its nested tags in every note of the vault. The edit is annotated so
+    /// that clients supporting it show a preview and ask for confirmation first.
+    async fn rename_tag(
+        &self,
+        old: &str,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>, tower_lsp::jsonrpc::Error> {
+        let new = new_name.trim().trim_start_matches('#');
+        if !tags::is_valid_tag(new) {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "`{}` is not a valid tag name",
+                new_name
+            )));
+        }
+        let occurrences = self.tag_occurrences().await;
+        let changes = tags::tag_rename_edits(occurrences, old, new);
+        if !self.supports_change_annotations().await {
+            return Ok(Some(WorkspaceEdit {
+                changes: Some(changes),
+                ..Default::default()
+            }));
+        }
+        let edits: usize = changes.values().map(Vec::len).sum();
+        let annotation = ChangeAnnotation {
+            label: format!("Rename #{} to #{}", old, new),
+            needs_confirmation: Some(true),
+            description: Some(format!("{} tags in {} notes", edits, changes.len())),
+        };
+        Ok(Some(rename::build_annotated_edit(changes, annotation)))
+    }
+
+    /// Looks up the database record of the note backing `uri`, if any.
+    async fn file_info_for_uri(&self, uri: &Url) -> Option<db::FileInfo> {
+        let local_path = uri.to_file_path().ok()?;
+        let local_path = local_path.to_str()?;
+        match self.db.get_all_file_infos().await {
+            Ok(infos) => infos.into_iter().find(|f| f.path == local_path),
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error retrieving file infos: {}", e),
+                    )
+                    .await;
+                None
+            }
+        }
+    }
+
+    /// Returns a Location pointing at the top of the note with the given virtual path.
+    async fn note_location(&self, virtual_path: &str) -> Option<Location> {
+        let infos = self.db.get_all_file_infos().await.ok()?;
+        let info = infos.into_iter().find(|f| f.virtual_path == virtual_path)?;
+        let uri = Url::from_file_path(&info.path).ok()?;
+        Some(Location {
+            uri,
+            range: Range::default(),
+        })
+    }
+
+    /// Gives the record of a renamed note its new virtual path, local path and title, once the
+    /// client applied the rename.
+    async fn record_rename(
+        &self,
+        old_virtual_path: &str,
+        new_virtual_path: &str,
+        new_path: &str,
+        new_title: Option<&str>,
+    ) {
+        if let Err(e) = self
+            .db
+            .rename_file(old_virtual_path, new_virtual_path, new_path, new_title)
+            .await
+        {
+            self.client
+                .log_message(
+                    MessageType::ERROR,
+                    format!("Error renaming {} in DB: {}", old_virtual_path, e),
+                )
+                .await;
+        }
+        self.ref_index.invalidate(old_virtual_path).await;
+        self.ref_index.invalidate(new_virtual_path).await;
+        self.note_index.invalidate().await;
+    }
+
+    /// Resolves the notes moved by a `workspace/*RenameFiles` request.
+    async fn moved_notes(&self, files: &[FileRename]) -> Vec<rename::MovedNote> {
+        let renames: Vec<(PathBuf, PathBuf)> = files
+            .iter()
+            .filter_map(|f| {
+                let old_path = Url::parse(&f.old_uri).ok()?.to_file_path().ok()?;
+                let new_path = Url::parse(&f.new_uri).ok()?.to_file_path().ok()?;
+                Some((old_path, new_path))
+            })
+            .collect();
+        match self.db.get_all_file_infos().await {
+            Ok(infos) => rename::moved_notes(&infos, &renames),
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error retrieving file infos: {}", e),
+                    )
+                    .await;
+                Vec::new()
+            }
+        }
+    }
+
+    /// Creates the markdown file of a new note under the workspace root, seeds it with a title
+    /// heading, registers it in the database and opens it in the editor.
+    async fn create_note(&self, virtual_path: &str, title: &str) {
+        let root = match tokio::fs::canonicalize(&self.ref_index.workspace_root).await {
+            Ok(root) => root,
+            Err(e) => {
+                self.client
+                    .show_message(
+                        MessageType::ERROR,
+                        format!("Cannot resolve the workspace root: {}", e),
+                    )
+                    .await;
+                return;
+            }
+        };
+        let Some(path) = code_actions::new_note_path(&root, virtual_path) else {
+            self.client
+                .show_message(
+                    MessageType::ERROR,
+                    format!("[[{}]] points outside of the workspace", virtual_path),
+                )
+                .await;
+            return;
+        };
+        if path.exists() {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("{} already exists", path.display()),
+                )
+                .await;
+            return;
+        }
+        if let Some(parent) = path.parent() {
+            if let Err(e) = tokio::fs::create_dir_all(parent).await {
+                self.client
+                    .show_message(
+                        MessageType::ERROR,
+                        format!("Cannot create {}: {}", parent.display(), e),
+                    )
+                    .await;
+                return;
+            }
+        }
+        if let Err(e) = tokio::fs::write(&path, format!("# {}\n", title)).await {
+            self.client
+                .show_message(
+                    MessageType::ERROR,
+                    format!("Cannot create {}: {}", path.display(), e),
+                )
+                .await;
+            return;
+        }
+
+        let path_str = path.to_string_lossy();
+        if let Err(e) = self.db.insert_file(virtual_path, title, &path_str).await {
+            self.client
+                .log_message(
+                    MessageType::ERROR,
+                    format!("Error registering {} in DB: {}", path_str, e),
+                )
+                .await;
+        }
+        self.ref_index.invalidate(virtual_path).await;
+        self.note_index.invalidate().await;
+        self.refresh_diagnostics().await;
+        self.refresh_semantic_tokens().await;
+
+        if let Ok(uri) = Url::from_file_path(&path) {
+            let params = ShowDocumentParams {
+                uri,
+                external: Some(false),
+                take_focus: Some(true),
+                selection: None,
+            };
+            if let Err(e) = self.client.show_document(params).await {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Could not open {}: {}", path_str, e),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Checks the wiki-links of a document against the database and publishes the result.
+    /// Clients supporting pull diagnostics ask for them, so nothing is pushed to them.
+    async fn publish_diagnostics(&self, uri: Url, version: i32) {
+        if self.uses_pull_diagnostics().await {
+            return;
+        }
+        let Some(note) = self.note(&uri).await else {
+            return;
+        };
+        let config = self.config.read().await.diagnostics.clone();
+        let aliases = self.aliases().await;
+        let diagnostics =
+            diagnostics::get_diagnostics(&note, self.db.as_ref(), aliases, &config).await;
+        self.client
+            .publish_diagnostics(uri, diagnostics, Some(version))
+            .await;
+    }
+
+    /// Re-publishes diagnostics for every open document, e.g. after a settings change. Clients
+    /// pulling diagnostics are asked to pull them again instead.
+    async fn refresh_diagnostics(&self) {
+        if self.uses_pull_diagnostics().await {
+            let supported = self
+                .client_capabilities
+                .read()
+                .await
+                .workspace
+                .as_ref()
+                .and_then(|w| w.diagnostic.as_ref())
+                .and_then(|d| d.refresh_support)
+                .unwrap_or(false);
+            if supported {
+                if let Err(e) = self.client.workspace_diagnostic_refresh().await {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("Could not refresh diagnostics: {}", e),
+                        )
+                        .await;
+                }
+            }
+            return;
+        }
+        for (uri, doc) in self.documents.all().await {
+            self.publish_diagnostics(uri, doc.version).await;
+        }
+    }
+
+    /// Whether the client requests diagnostics (`textDocument/diagnostic`) rather than waiting
+    /// for them to be published.
+    async fn uses_pull_diagnostics(&self) -> bool {
+        let caps = self.client_capabilities.read().await;
+        caps.text_document
+            .as_ref()
+            .is_some_and(|t| t.diagnostic.is_some())
+    }
+
+    /// Returns the parsed note at `uri` with its version when it is open in the editor, or
+    /// reads it from disk otherwise.
+    async fn note_for_diagnostics(&self, uri: &Url) -> Option<(Arc<ParsedNote>, Option<i32>)> {
+        if let Some(doc) = self.documents.get(uri).await {
+            return Some((self.note(uri).await?, Some(doc.version)));
+        }
+        let path = uri.to_file_path().ok()?;
+        match tokio::fs::read_to_string(&path).await {
+            Ok(text) => Some((
+                Arc::new(ParsedNote::parse(&text, self.encoding().await)),
+                None,
+            )),
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Could not read {}: {}", path.display(), e),
+                    )
+                    .await;
+                None
+            }
+        }
+    }
+
+    /// Virtual paths of the notes in the database, or `None` when it cannot be queried.
+    async fn known_notes(&self) -> Option<HashSet<String>> {
+        if !self.db.is_available() {
+            return None;
+        }
+        match self.db.get_all_file_infos().await {
+            Ok(infos) => {
+                let aliases = self.aliases().await;
+                Some(
+                    infos
+                        .into_iter()
+                        .map(|f| f.virtual_path)
+                        .chain(aliases.keys().cloned())
+                        .collect(),
+                )
+            }
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error retrieving file infos: {}", e),
+                    )
+                    .await;
+                None
+            }
+        }
+    }
+
+    /// Asks the client to re-request semantic tokens after notes were added, moved or removed,
+    /// since links may have become resolved or unresolved.
+    async fn refresh_semantic_tokens(&self) {
+        let supported = self
+            .client_capabilities
+            .read()
+            .await
+            .workspace
+            .as_ref()
+            .and_then(|w| w.semantic_tokens.as_ref())
+            .and_then(|s| s.refresh_support)
+            .unwrap_or(false);
+        if supported {
+            if let Err(e) = self.client.semantic_tokens_refresh().await {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Could not refresh semantic tokens: {}", e),
+                    )
+                    .await;
+            }
         }
     }
 }
@@ -44,16 +490,34 @@ impl Backend {
 impl LanguageServer for Backend {
     async fn initialize(
         &self,
-        _params: InitializeParams,
+        params: InitializeParams,
     ) -> Result<InitializeResult, tower_lsp::jsonrpc::Error> {
+        *self.config.write().await = Config::from_value(params.initialization_options);
+        let encoding = PositionEncoding::negotiate(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|g| g.position_encodings.as_deref()),
+        );
+        *self.position_encoding.write().await = encoding;
+        *self.client_capabilities.write().await = params.capabilities;
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                position_encoding: Some(encoding.kind()),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        // Saves refresh the aliases of the vault.
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
                 )),
                 completion_provider: Some(CompletionOptions {
-                    resolve_provider: Some(false),
-                    trigger_characters: Some(vec!["[".into()]),
+                    resolve_provider: Some(true),
+                    trigger_characters: Some(vec!["[".into(), "#".into(), "^".into()]),
                     ..Default::default()
                 }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
@@ -62,6 +526,69 @@ impl LanguageServer for Backend {
                 // Advertise workspace symbol support.
                 workspace_symbol_provider: Some(OneOf::Left(true)),
                 definition_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "\n".to_string(),
+                    more_trigger_character: Some(vec!["[".to_string()]),
+                }),
+                references_provider: Some(OneOf::Left(true)),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("gnosis".to_string()),
+                        // Links are checked against other notes.
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                )),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        code_actions::COPY_BLOCK_LINK.to_string(),
+                        code_actions::CREATE_NOTE.to_string(),
+                    ],
+                    ..Default::default()
+                }),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                })),
+                // Follow notes moved or deleted from the editor's file explorer.
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: None,
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        will_rename: Some(note_file_operation_filters()),
+                        did_rename: Some(note_file_operation_filters()),
+                        did_delete: Some(note_file_operation_filters()),
+                        ..Default::default()
+                    }),
+                }),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic_tokens::legend(),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                            range: None,
+                            ..Default::default()
+                        },
+                    ),
+                ),
                 code_lens_provider: Some(CodeLensOptions {
                     resolve_provider: Some(false),
                 }),
@@ -92,61 +619,253 @@ impl LanguageServer for Backend {
         Ok(())
     }
 
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let updated = self.config.read().await.updated(params.settings);
+        *self.config.write().await = updated;
+        self.refresh_diagnostics().await;
+    }
+
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let uri = params.text_document.uri.clone();
         let text = params.text_document.text;
-        {
-            let mut docs = self.documents.lock().await;
-            docs.insert(uri.clone(), text);
-        }
+        self.documents
+            .open(uri.clone(), &text, params.text_document.version)
+            .await;
         self.client
             .log_message(MessageType::INFO, format!("Opened file: {}", uri))
             .await;
+        self.publish_diagnostics(uri, params.text_document.version)
+            .await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let uri = params.text_document.uri.clone();
-        if let Some(change) = params.content_changes.into_iter().last() {
-            let text = change.text;
-            let mut docs = self.documents.lock().await;
-            docs.insert(uri.clone(), text);
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        match self
+            .documents
+            .change(&uri, params.content_changes, version, self.encoding().await)
+            .await
+        {
+            Some(doc) => self.publish_diagnostics(uri, doc.version).await,
+            None => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Change for a document that is not open: {}", uri),
+                    )
+                    .await
+            }
         }
     }
 
+    async fn did_save(&self, _params: DidSaveTextDocumentParams) {
+        self.note_index.invalidate().await;
+        // Links to aliases added or removed by the save resolve differently.
+        self.refresh_diagnostics().await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.close(&uri).await;
+        // Unsaved changes to the aliases of the note are gone.
+        self.note_index.invalidate().await;
+        self.semantic_tokens.remove(&uri).await;
+        // Diagnostics of closed documents would otherwise linger in the editor.
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
+
     async fn completion(
         &self,
         params: CompletionParams,
     ) -> Result<Option<CompletionResponse>, tower_lsp::jsonrpc::Error> {
         info!("Completion requested: {:?}", params);
 
-        let infos = match self.db.get_all_file_infos().await {
-            Ok(infos) => infos,
-            Err(e) => {
-                self.client
-                    .log_message(
-                        MessageType::ERROR,
-                        format!("Error querying DB for completions: {}", e),
-                    )
-                    .await;
+        // After `[[note#`, offer the headings (or, after `#^`, the block ids) of the target note.
+        let pos = params.text_document_position.position;
+        let uri = &params.text_document_position.text_document.uri;
+        let Some(note) = self.note(uri).await else {
+            return Ok(None);
+        };
+        // Nothing is completed inside code.
+        if note.in_code(note.offset(pos)) {
+            return Ok(None);
+        }
+        let line = note.line(pos.line as usize).unwrap_or_default();
+        let encoding = self.encoding().await;
+        let col = encoding.byte_offset(line, pos.character);
+        if let Some(ctx) = completion::link_context(&line[..col]) {
+            if let Some((virtual_path, partial_anchor)) = ctx.target.split_once('#') {
+                let anchor_start = ctx.target_start + virtual_path.len() + 1;
+                let range = encoding.range(pos.line as usize, line, anchor_start, col);
+                let items = completion::anchor_completions(
+                    self.db.as_ref(),
+                    virtual_path.trim(),
+                    partial_anchor,
+                    range,
+                )
+                .await;
+                return Ok(Some(CompletionResponse::Array(items)));
+            }
+        }
+        // Notes are only offered inside `[[`; elsewhere `#` starts a tag, except in headings
+        // and the frontmatter.
+        let Some(ctx) = completion::link_context(&line[..col]) else {
+            let offset = note.offset(pos);
+            let in_heading = note
+                .headings
+                .iter()
+                .map(|h| &h.span)
+                .chain(note.frontmatter.iter().map(|f| &f.span))
+                .any(|span| span.start <= offset && offset <= span.end);
+            let Some(tag_start) = tags::tag_context(&line[..col]).filter(|_| !in_heading) else {
                 return Ok(None);
+            };
+            let range = encoding.range(pos.line as usize, line, tag_start, col);
+            // The tag being typed is not a suggestion.
+            let mut occurrences = self.tag_occurrences().await;
+            occurrences.retain(|o| !(o.location.uri == *uri && o.location.range.end == pos));
+            let items = tags::tag_completions(&occurrences, range);
+            return Ok(Some(CompletionResponse::Array(items)));
+        };
+        // The partial target extends past the cursor when the link is already closed, as in
+        // `[[me|eting]]`; it is replaced as a whole.
+        let rest = &line[col..];
+        let closing = rest.find("]]").filter(|&end| !rest[..end].contains("[["));
+        let (target_end, end) = match closing {
+            Some(closing) => {
+                let target_len = rest[..closing].find(['|', '#']).unwrap_or(closing);
+                let end = if target_len < closing {
+                    completion::LinkEnd::Anchored
+                } else {
+                    completion::LinkEnd::Closed
+                };
+                (col + target_len, end)
             }
+            None => (col, completion::LinkEnd::Open),
         };
+        let range = encoding.range(pos.line as usize, line, ctx.target_start, target_end);
+        let notes = self.indexed_notes().await;
+        let current = notes
+            .iter()
+            .find(|n| n.uri == *uri)
+            .map(|n| n.info.virtual_path.as_str());
+        let list = completion::note_completions(
+            &notes,
+            &line[ctx.target_start..target_end],
+            current,
+            range,
+            end,
+        );
+        Ok(Some(CompletionResponse::List(list)))
+    }
 
-        let items: Vec<CompletionItem> = infos
-            .into_iter()
-            .map(|info| {
-                let insert_text = format!("{}|{}", info.virtual_path, info.title);
-                CompletionItem {
-                    label: format!("{} ({})", info.title, info.virtual_path),
-                    kind: Some(CompletionItemKind::FILE),
-                    detail: Some(format!("Insert wiki-link for file: {}", info.virtual_path)),
-                    insert_text: Some(insert_text),
-                    ..Default::default()
-                }
+    async fn completion_resolve(
+        &self,
+        item: CompletionItem,
+    ) -> Result<CompletionItem, tower_lsp::jsonrpc::Error> {
+        let config = self.config.read().await.hover.clone();
+        let encoding = self.encoding().await;
+        let open_notes = self.documents.notes(encoding).await;
+        Ok(completion::resolve_note_completion(
+            item,
+            self.db.as_ref(),
+            self.ref_index.as_ref(),
+            &open_notes,
+            encoding,
+            &config,
+        )
+        .await)
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult, tower_lsp::jsonrpc::Error> {
+        let uri = params.text_document.uri;
+        let config = self.config.read().await.diagnostics.clone();
+        let items = match self.note_for_diagnostics(&uri).await {
+            Some((note, _)) => {
+                let aliases = self.aliases().await;
+                diagnostics::get_diagnostics(&note, self.db.as_ref(), aliases, &config).await
+            }
+            None => Vec::new(),
+        };
+        let result_id = diagnostics::result_id(&items);
+        let report = if params.previous_result_id.as_deref() == Some(result_id.as_str()) {
+            DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id,
+                },
+            })
+        } else {
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id),
+                    items,
+                },
             })
+        };
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    /// Reports on every note of the `files` table, open or not.
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult, tower_lsp::jsonrpc::Error> {
+        let previous: HashMap<Url, String> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|p| (p.uri, p.value))
+            .collect();
+        let aliases = self.aliases().await;
+        let Some(mut targets) = diagnostics::LinkTargets::load(self.db.as_ref(), aliases).await
+        else {
+            return Ok(WorkspaceDiagnosticReportResult::Report(
+                WorkspaceDiagnosticReport { items: Vec::new() },
+            ));
+        };
+        let config = self.config.read().await.diagnostics.clone();
+        let uris: Vec<Url> = targets
+            .paths()
+            .filter_map(|path| Url::from_file_path(path).ok())
             .collect();
 
-        Ok(Some(CompletionResponse::Array(items)))
+        let mut reports = Vec::new();
+        for uri in uris {
+            let Some((note, version)) = self.note_for_diagnostics(&uri).await else {
+                continue;
+            };
+            let items = diagnostics::check_links(&note, &mut targets, &config).await;
+            let result_id = diagnostics::result_id(&items);
+            let version = version.map(i64::from);
+            let report = if previous.get(&uri) == Some(&result_id) {
+                WorkspaceDocumentDiagnosticReport::Unchanged(
+                    WorkspaceUnchangedDocumentDiagnosticReport {
+                        uri,
+                        version,
+                        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                            result_id,
+                        },
+                    },
+                )
+            } else {
+                WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                    uri,
+                    version,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: Some(result_id),
+                        items,
+                    },
+                })
+            };
+            reports.push(report);
+        }
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items: reports },
+        ))
     }
 
     async fn document_symbol(
@@ -154,54 +873,120 @@ impl LanguageServer for Backend {
         params: DocumentSymbolParams,
     ) -> Result<Option<DocumentSymbolResponse>, tower_lsp::jsonrpc::Error> {
         let uri = params.text_document.uri;
-        let docs = self.documents.lock().await;
-        let text = match docs.get(&uri) {
-            Some(text) => text.clone(),
-            None => {
-                self.client
-                    .log_message(MessageType::ERROR, format!("Document not found: {}", uri))
-                    .await;
-                return Ok(None);
-            }
+        let Some(note) = self.note(&uri).await else {
+            self.client
+                .log_message(MessageType::ERROR, format!("Document not found: {}", uri))
+                .await;
+            return Ok(None);
         };
 
-        let symbols = document_symbols::extract_symbols(&text);
+        let symbols = document_symbols::extract_symbols(&note);
         Ok(Some(DocumentSymbolResponse::Nested(symbols)))
     }
 
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> Result<Option<Vec<FoldingRange>>, tower_lsp::jsonrpc::Error> {
+        let Some(note) = self.note(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+        Ok(Some(folding_ranges::get_folding_ranges(&note)))
+    }
+
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
+        let Some(note) = self.note(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+        let config = self.config.read().await.formatting.clone();
+        Ok(Some(formatting::format_note(&note, &config, None)))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
+        let Some(note) = self.note(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+        let config = self.config.read().await.formatting.clone();
+        Ok(Some(formatting::format_note(
+            &note,
+            &config,
+            Some(params.range),
+        )))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
+        let position = params.text_document_position;
+        let Some(note) = self.note(&position.text_document.uri).await else {
+            return Ok(None);
+        };
+        Ok(Some(on_type_formatting::get_on_type_edits(
+            &note,
+            position.position,
+            &params.ch,
+        )))
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>, tower_lsp::jsonrpc::Error> {
+        let Some(note) = self.note(&params.text_document.uri).await else {
+            return Ok(None);
+        };
+        let ranges = params
+            .positions
+            .into_iter()
+            .map(|pos| selection_range::get_selection_range(&note, pos))
+            .collect();
+        Ok(Some(ranges))
+    }
+
     async fn symbol(
         &self,
         params: WorkspaceSymbolParams,
     ) -> Result<Option<Vec<SymbolInformation>>, tower_lsp::jsonrpc::Error> {
         let query = params.query;
-        let symbols = workspace_symbols::get_workspace_symbols(self.db.clone(), &query).await;
+        let symbols = workspace_symbols::get_workspace_symbols(
+            self.db.clone(),
+            &query,
+            self.encoding().await,
+        )
+        .await;
         Ok(Some(symbols))
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>, tower_lsp::jsonrpc::Error> {
         let position = params.text_document_position_params.position;
         let uri = params.text_document_position_params.text_document.uri;
-        let docs = self.documents.lock().await;
-        let text = match docs.get(&uri) {
-            Some(t) => t,
-            None => return Ok(None),
-        };
+        if let Some(tag) = self.tag_at(&uri, position).await {
+            let occurrences = self.tag_occurrences().await;
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: tags::tag_hover(&occurrences, &tag.value.name),
+                }),
+                range: None,
+            }));
+        }
 
-        // Get the text line at the hover position.
-        let lines: Vec<&str> = text.lines().collect();
-        if (position.line as usize) >= lines.len() {
+        // Get the wiki-link at the hover position.
+        let Some(link) = self.wiki_link_at(&uri, position).await else {
             return Ok(None);
-        }
-        let line = lines[position.line as usize];
+        };
 
         // Use the dedicated module to get a hover preview.
-        if let Some(hover) =
-            hover_preview::get_hover_preview(line, position.character as usize, self.db.as_ref())
-                .await
-        {
-            return Ok(Some(hover));
-        }
-        Ok(None)
+        let link = self.resolve_alias(link.value).await;
+        let config = self.config.read().await.hover.clone();
+        Ok(hover_preview::get_hover_preview(&link, self.db.as_ref(), &config).await)
     }
 
     async fn goto_definition(
@@ -211,25 +996,554 @@ impl LanguageServer for Backend {
         // Get the document URI and position.
         let pos = params.text_document_position_params.position;
         let uri = params.text_document_position_params.text_document.uri;
-        let docs = self.documents.lock().await;
-        let text = match docs.get(&uri) {
-            Some(text) => text,
+        // Get the wiki-link at the given position.
+        let Some(link) = self.wiki_link_at(&uri, pos).await else {
+            return Ok(None);
+        };
+        let link = self.resolve_alias(link.value).await;
+        // Use our goto-definition module to get a Location.
+        if let Some(loc) = goto_definition::get_goto_definition(&link, self.db.as_ref()).await {
+            Ok(Some(GotoDefinitionResponse::Scalar(loc)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn references(
+        &self,
+        params: ReferenceParams,
+    ) -> Result<Option<Vec<Location>>, tower_lsp::jsonrpc::Error> {
+        let pos = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
+
+        // A tag lists every use of the tag and its nested tags across the vault.
+        if let Some(tag) = self.tag_at(&uri, pos).await {
+            let locations = self
+                .tag_occurrences()
+                .await
+                .into_iter()
+                .filter(|o| tags::is_tag_or_child(&o.name, &tag.value.name))
+                .map(|o| o.location)
+                .collect();
+            return Ok(Some(locations));
+        }
+
+        // A wiki-link under the cursor takes precedence; anywhere else in the note we list the
+        // backlinks of the note itself.
+        let virtual_path = match self.wiki_link_at(&uri, pos).await {
+            Some(link) => link.value.virtual_path,
+            None => match self.file_info_for_uri(&uri).await {
+                Some(info) => info.virtual_path,
+                None => return Ok(None),
+            },
+        };
+
+        let encoding = self.encoding().await;
+        let open_notes = self.documents.notes(encoding).await;
+        let mut locations = match self
+            .ref_index
+            .find_references(&virtual_path, &open_notes, encoding)
+            .await
+        {
+            Ok(locations) => locations,
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error searching references to {}: {}", virtual_path, e),
+                    )
+                    .await;
+                return Ok(None);
+            }
+        };
+
+        if params.context.include_declaration {
+            if let Some(loc) = self.note_location(&virtual_path).await {
+                locations.insert(0, loc);
+            }
+        }
+
+        Ok(Some(locations))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>, tower_lsp::jsonrpc::Error> {
+        let pos = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+        let Some(note) = self.note(&uri).await else {
+            return Ok(None);
+        };
+        let highlights = document_highlight::get_highlights(&note, pos);
+        Ok((!highlights.is_empty()).then_some(highlights))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>, tower_lsp::jsonrpc::Error> {
+        let pos = params.position;
+        let uri = params.text_document.uri;
+
+        if let Some(tag) = self.tag_at(&uri, pos).await {
+            let mut range = tag.range;
+            // The `#` is not part of the name being edited.
+            if !tag.value.in_frontmatter {
+                range.start.character += 1;
+            }
+            return Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+                range,
+                placeholder: tag.value.name,
+            }));
+        }
+
+        if let Some(link) = self.wiki_link_at(&uri, pos).await {
+            return Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+                range: link.range,
+                placeholder: link.value.virtual_path,
+            }));
+        }
+
+        // At the top of a note, the note itself is renamed.
+        if pos.line == 0 {
+            if let Some(info) = self.file_info_for_uri(&uri).await {
+                let first_line = self
+                    .documents
+                    .get(&uri)
+                    .await
+                    .and_then(|doc| doc.line(0))
+                    .unwrap_or_default();
+                let encoding = self.encoding().await;
+                return Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+                    range: encoding.range(0, &first_line, 0, first_line.len()),
+                    placeholder: info.virtual_path,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Renames a note. The new name is either `new/path` or `new/path|New Title`; in the latter
+    /// form, link aliases equal to the old title are rewritten to the new title. When the file
+    /// moves, the edit is returned to the client; otherwise the server applies it through
+    /// `workspace/applyEdit` so that the record is only updated once the edit is applied.
+    async fn rename(
+        &self,
+        params: RenameParams,
+    ) -> Result<Option<WorkspaceEdit>, tower_lsp::jsonrpc::Error> {
+        let pos = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
+
+        if let Some(tag) = self.tag_at(&uri, pos).await {
+            return self.rename_tag(&tag.value.name, &params.new_name).await;
+        }
+
+        let old_virtual_path = match self.wiki_link_at(&uri, pos).await {
+            Some(link) => link.value.virtual_path,
+            None if pos.line == 0 => match self.file_info_for_uri(&uri).await {
+                Some(info) => info.virtual_path,
+                None => return Ok(None),
+            },
             None => return Ok(None),
         };
-        // Get the text line at the given position.
-        let lines: Vec<&str> = text.lines().collect();
-        if (pos.line as usize) >= lines.len() {
+        let (new_virtual_path, new_title) = rename::parse_new_name(&params.new_name);
+        let infos = match self.db.get_all_file_infos().await {
+            Ok(infos) => infos,
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error retrieving file infos: {}", e),
+                    )
+                    .await;
+                Vec::new()
+            }
+        };
+        rename::check_new_virtual_path(&new_virtual_path, &old_virtual_path, &infos)
+            .map_err(tower_lsp::jsonrpc::Error::invalid_params)?;
+        let target = infos
+            .into_iter()
+            .find(|f| f.virtual_path == old_virtual_path);
+
+        let rewrite = rename::LinkRewrite {
+            old_virtual_path: &old_virtual_path,
+            new_virtual_path: &new_virtual_path,
+            retitle: match (&target, &new_title) {
+                (Some(info), Some(title)) => Some((info.title.as_str(), title.as_str())),
+                _ => None,
+            },
+        };
+        let encoding = self.encoding().await;
+        let open_notes = self.documents.notes(encoding).await;
+        let changes =
+            rename::link_rewrite_edits(&self.ref_index, &open_notes, &rewrite, encoding).await;
+
+        let Some(info) = target else {
+            return Ok(Some(rename::build_workspace_edit(changes, None)));
+        };
+
+        // Nothing is recorded before the client applies the edit: a move is recorded by
+        // `didRenameFiles`, a rename keeping the file in place once the applied edit is confirmed.
+        if self
+            .supports_resource_operation(ResourceOperationKind::Rename)
+            .await
+        {
+            let new_path = rename::renamed_note_path(&info, &new_virtual_path);
+            if new_path != Path::new(&info.path) && new_path.exists() {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "{} already exists",
+                    new_path.display()
+                )));
+            }
+            if let (Ok(old_uri), Ok(new_uri)) = (
+                Url::from_file_path(&info.path),
+                Url::from_file_path(&new_path),
+            ) {
+                if old_uri != new_uri {
+                    self.pending_renames.write().await.insert(
+                        info.path.clone(),
+                        rename::PendingRename {
+                            new_path,
+                            new_virtual_path,
+                            new_title,
+                        },
+                    );
+                    return Ok(Some(rename::build_workspace_edit(
+                        changes,
+                        Some((old_uri, new_uri)),
+                    )));
+                }
+            }
+        }
+
+        let edit = if self.supports_change_annotations().await {
+            let annotation = ChangeAnnotation {
+                label: format!("Rename {} to {}", old_virtual_path, new_virtual_path),
+                needs_confirmation: Some(true),
+                description: None,
+            };
+            rename::build_annotated_edit(changes, annotation)
+        } else {
+            rename::build_workspace_edit(changes, None)
+        };
+        match self.client.apply_edit(edit).await {
+            Ok(response) if response.applied => {
+                self.record_rename(
+                    &old_virtual_path,
+                    &new_virtual_path,
+                    &info.path,
+                    new_title.as_deref(),
+                )
+                .await;
+                self.refresh_diagnostics().await;
+                self.refresh_semantic_tokens().await;
+            }
+            Ok(response) => {
+                self.client
+                    .log_message(
+                        MessageType::INFO,
+                        format!(
+                            "Rename of {} not applied: {}",
+                            old_virtual_path,
+                            response.failure_reason.unwrap_or_default()
+                        ),
+                    )
+                    .await;
+            }
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Could not apply the rename of {}: {}", old_virtual_path, e),
+                    )
+                    .await;
+            }
+        }
+        // The edit went through `workspace/applyEdit`; nothing is left for the client to apply.
+        Ok(Some(WorkspaceEdit::default()))
+    }
+
+    async fn will_rename_files(
+        &self,
+        params: RenameFilesParams,
+    ) -> Result<Option<WorkspaceEdit>, tower_lsp::jsonrpc::Error> {
+        // The links to a note moved by `textDocument/rename` are already part of that edit.
+        let pending = self.pending_renames.read().await;
+        let moved: Vec<rename::MovedNote> = self
+            .moved_notes(&params.files)
+            .await
+            .into_iter()
+            .filter(|note| {
+                pending
+                    .get(&note.info.path)
+                    .is_none_or(|p| p.new_path != note.new_path)
+            })
+            .collect();
+        drop(pending);
+        if moved.is_empty() {
             return Ok(None);
         }
-        let line = lines[pos.line as usize];
-        // Use our goto-definition module to get a Location.
-        if let Some(loc) =
-            goto_definition::get_goto_definition(line, pos.character as usize, self.db.as_ref())
+
+        let encoding = self.encoding().await;
+        let open_notes = self.documents.notes(encoding).await;
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for note in &moved {
+            let rewrite = rename::LinkRewrite {
+                old_virtual_path: &note.info.virtual_path,
+                new_virtual_path: &note.new_virtual_path,
+                retitle: None,
+            };
+            for (uri, edits) in
+                rename::link_rewrite_edits(&self.ref_index, &open_notes, &rewrite, encoding).await
+            {
+                changes.entry(uri).or_default().extend(edits);
+            }
+        }
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(rename::build_workspace_edit(changes, None)))
+    }
+
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        for note in self.moved_notes(&params.files).await {
+            let new_path = note.new_path.to_string_lossy();
+            let pending = self
+                .pending_renames
+                .write()
+                .await
+                .remove(&note.info.path)
+                .filter(|p| p.new_path == note.new_path);
+            if let Some(pending) = pending {
+                self.record_rename(
+                    &note.info.virtual_path,
+                    &pending.new_virtual_path,
+                    &new_path,
+                    pending.new_title.as_deref(),
+                )
+                .await;
+                continue;
+            }
+            if let Err(e) = self
+                .db
+                .update_file_location(&note.info.path, &new_path, &note.new_virtual_path)
                 .await
+            {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error updating {} in DB: {}", note.info.path, e),
+                    )
+                    .await;
+            }
+            self.ref_index.invalidate(&note.info.virtual_path).await;
+            self.ref_index.invalidate(&note.new_virtual_path).await;
+        }
+        self.note_index.invalidate().await;
+        self.refresh_diagnostics().await;
+        self.refresh_semantic_tokens().await;
+    }
+
+    async fn did_delete_files(&self, params: DeleteFilesParams) {
+        let deleted: Vec<PathBuf> = params
+            .files
+            .iter()
+            .filter_map(|f| Url::parse(&f.uri).ok()?.to_file_path().ok())
+            .collect();
+        let infos = match self.db.get_all_file_infos().await {
+            Ok(infos) => infos,
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error retrieving file infos: {}", e),
+                    )
+                    .await;
+                return;
+            }
+        };
+        // Deleting a folder deletes every note below it.
+        for info in infos
+            .iter()
+            .filter(|info| deleted.iter().any(|d| Path::new(&info.path).starts_with(d)))
         {
-            Ok(Some(GotoDefinitionResponse::Scalar(loc)))
-        } else {
-            Ok(None)
+            if let Err(e) = self.db.delete_file(&info.path).await {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Error deleting {} from DB: {}", info.path, e),
+                    )
+                    .await;
+            }
+            self.ref_index.invalidate(&info.virtual_path).await;
+        }
+        self.note_index.invalidate().await;
+        self.refresh_diagnostics().await;
+        self.refresh_semantic_tokens().await;
+    }
+
+    async fn document_link(
+        &self,
+        params: DocumentLinkParams,
+    ) -> Result<Option<Vec<DocumentLink>>, tower_lsp::jsonrpc::Error> {
+        let uri = params.text_document.uri;
+        let Some(note) = self.note(&uri).await else {
+            return Ok(None);
+        };
+        let links = document_links::get_document_links(
+            &uri,
+            &note,
+            self.db.as_ref(),
+            &self.note_index,
+            &self.documents,
+        )
+        .await;
+        Ok(Some(links))
+    }
+
+    async fn document_link_resolve(
+        &self,
+        params: DocumentLink,
+    ) -> Result<DocumentLink, tower_lsp::jsonrpc::Error> {
+        Ok(document_links::resolve_document_link(
+            params,
+            self.db.as_ref(),
+            &self.note_index,
+            &self.documents,
+            self.encoding().await,
+        )
+        .await)
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>, tower_lsp::jsonrpc::Error> {
+        let uri = params.text_document.uri;
+        let Some(note) = self.note(&uri).await else {
+            return Ok(None);
+        };
+        let known_notes = self.known_notes().await;
+        let data = semantic_tokens::get_semantic_tokens(&note, known_notes.as_ref());
+        let tokens = self.semantic_tokens.full(&uri, data).await;
+        Ok(Some(SemanticTokensResult::Tokens(tokens)))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>, tower_lsp::jsonrpc::Error> {
+        let uri = params.text_document.uri;
+        let Some(note) = self.note(&uri).await else {
+            return Ok(None);
+        };
+        let known_notes = self.known_notes().await;
+        let data = semantic_tokens::get_semantic_tokens(&note, known_notes.as_ref());
+        let result = self
+            .semantic_tokens
+            .delta(&uri, &params.previous_result_id, data)
+            .await;
+        Ok(Some(result))
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> Result<Option<CodeActionResponse>, tower_lsp::jsonrpc::Error> {
+        let uri = params.text_document.uri;
+        let Some(note) = self.note(&uri).await else {
+            return Ok(None);
+        };
+        // Links use the note's virtual path; notes missing from the DB fall back to the file name.
+        let virtual_path = match self.file_info_for_uri(&uri).await {
+            Some(info) => info.virtual_path,
+            None => uri
+                .to_file_path()
+                .ok()
+                .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+                .unwrap_or_default(),
+        };
+
+        let mut actions = Vec::new();
+
+        // An unresolved wiki-link under the cursor can be fixed by creating its note.
+        if let Some(Spanned { value: link, .. }) = self.wiki_link_at(&uri, params.range.start).await
+        {
+            if self.db.is_available()
+                && self.note_location(&link.virtual_path).await.is_none()
+                && code_actions::new_note_path(
+                    Path::new(&self.ref_index.workspace_root),
+                    &link.virtual_path,
+                )
+                .is_some()
+            {
+                let fixed: Vec<Diagnostic> = params
+                    .context
+                    .diagnostics
+                    .iter()
+                    .filter(|d| {
+                        d.code
+                            == Some(NumberOrString::String(
+                                diagnostics::BROKEN_LINK_CODE.to_string(),
+                            ))
+                            && d.range.start.line as usize == link.line
+                    })
+                    .cloned()
+                    .collect();
+                actions.push(CodeActionOrCommand::CodeAction(
+                    code_actions::create_note_action(
+                        &link.virtual_path,
+                        link.alias.as_deref(),
+                        fixed,
+                    ),
+                ));
+            }
+        }
+
+        if let Some(action) = code_actions::block_reference_action(
+            &uri,
+            &note,
+            params.range.start.line as usize,
+            &virtual_path,
+        ) {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+        Ok(Some(actions))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>, tower_lsp::jsonrpc::Error> {
+        match params.command.as_str() {
+            code_actions::COPY_BLOCK_LINK => {
+                let Some(link) = params.arguments.first().and_then(|a| a.as_str()) else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                        "Expected the block link as first argument",
+                    ));
+                };
+                // The server cannot reach the clipboard; the link is shown to the user and
+                // returned so client-side commands can copy it.
+                self.client
+                    .show_message(MessageType::INFO, format!("Block link: {}", link))
+                    .await;
+                Ok(Some(serde_json::Value::String(link.to_string())))
+            }
+            code_actions::CREATE_NOTE => {
+                let args: Vec<&str> = params.arguments.iter().filter_map(|a| a.as_str()).collect();
+                let [virtual_path, title] = args[..] else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                        "Expected the virtual path and title as arguments",
+                    ));
+                };
+                self.create_note(virtual_path, title).await;
+                Ok(None)
+            }
+            other => Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Unknown command: {}",
+                other
+            ))),
         }
     }
 
@@ -266,31 +1580,29 @@ impl LanguageServer for Backend {
         }
         let info = maybe_info.unwrap();
 
-        // For the workspace root, we assume a WORKSPACE_ROOT env var or default to the current directory.
-        let _workspace_root = std::env::var("WORKSPACE_ROOT").unwrap_or_else(|_| ".".to_string());
-
-        // Use the hybrid index to get the reference count.
-        let count = self
+        // Use the hybrid index to find the backlinks; the lens lists them when clicked.
+        let encoding = self.encoding().await;
+        let open_notes = self.documents.notes(encoding).await;
+        let locations = self
             .ref_index
-            .get_references_count(&info.virtual_path)
+            .find_references(&info.virtual_path, &open_notes, encoding)
             .await
-            .unwrap_or(0);
+            .unwrap_or_default();
+        let start = Position {
+            line: 0,
+            character: 0,
+        };
 
         let code_lens = CodeLens {
-            range: Range {
-                start: Position {
-                    line: 0,
-                    character: 0,
-                },
-                end: Position {
-                    line: 0,
-                    character: 0,
-                },
-            },
+            range: Range { start, end: start },
             command: Some(Command {
-                title: format!("Referenced {} times", count),
-                command: "dummy.showReferences".to_string(),
-                arguments: None,
+                title: format!("Referenced {} times", locations.len()),
+                command: SHOW_REFERENCES.to_string(),
+                arguments: Some(vec![
+                    serde_json::json!(uri),
+                    serde_json::json!(start),
+                    serde_json::json!(locations),
+                ]),
             }),
             data: None,
         };
@@ -336,9 +1648,11 @@ impl LanguageServer for Backend {
         let info = maybe_info.unwrap();
 
         // Get the reference count using your hybrid index.
+        let encoding = self.encoding().await;
+        let open_notes = self.documents.notes(encoding).await;
         let count = self
             .ref_index
-            .get_references_count(&info.virtual_path)
+            .get_references_count(&info.virtual_path, &open_notes, encoding)
             .await
             .unwrap_or(0);
 
@@ -363,6 +1677,24 @@ impl LanguageServer for Backend {
     }
 }
 
+/// Registration filters for the notes and folders whose file operations we follow.
+fn note_file_operation_filters() -> FileOperationRegistrationOptions {
+    let filter = |glob: &str, matches| FileOperationFilter {
+        scheme: Some("file".to_string()),
+        pattern: FileOperationPattern {
+            glob: glob.to_string(),
+            matches: Some(matches),
+            options: None,
+        },
+    };
+    FileOperationRegistrationOptions {
+        filters: vec![
+            filter("**/*.md", FileOperationPatternKind::File),
+            filter("**", FileOperationPatternKind::Folder),
+        ],
+    }
+}
+
 pub async fn run() {
     let db_instance = db::Database::new().await;
     let db_arc = Arc::new(db_instance);
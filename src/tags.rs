@@ -0,0 +1,207 @@
+// src/tags.rs
+
+use crate::note_index::IndexedNote;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Location, Range, TextEdit,
+};
+use url::Url;
+
+/// A tag found in a note of the vault.
+#[derive(Debug, Clone)]
+pub struct TagOccurrence {
+    /// The tag without its `#`.
+    pub name: String,
+    /// Frontmatter tags are written without their `#`.
+    pub in_frontmatter: bool,
+    pub location: Location,
+}
+
+/// Lists the tags of the notes of the vault.
+pub fn occurrences(notes: &[IndexedNote]) -> Vec<TagOccurrence> {
+    notes
+        .iter()
+        .flat_map(|note| {
+            note.summary.tags.iter().map(|tag| TagOccurrence {
+                name: tag.value.name.clone(),
+                in_frontmatter: tag.value.in_frontmatter,
+                location: Location {
+                    uri: note.uri.clone(),
+                    range: tag.range,
+                },
+            })
+        })
+        .collect()
+}
+
+fn partial_tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:^|\s)#([\p{L}\p{N}_/-]*)$").unwrap())
+}
+
+/// Finds the tag being typed at the end of `prefix` (the line up to the cursor) and returns the
+/// byte offset just past its `#`.
+pub fn tag_context(prefix: &str) -> Option<usize> {
+    partial_tag_regex()
+        .captures(prefix)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.start())
+}
+
+/// Checks a tag name typed by the user, without its `#`: letters, digits, `_`, `-` and `/`
+/// only, and not only digits.
+pub fn is_valid_tag(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().all(|c| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'))
+}
+
+/// The edits renaming the tag `old` to `new` wherever it is used, its nested tags included:
+/// renaming `proj` to `project` turns `#proj/alpha` into `#project/alpha`.
+pub fn tag_rename_edits(
+    occurrences: Vec<TagOccurrence>,
+    old: &str,
+    new: &str,
+) -> HashMap<Url, Vec<TextEdit>> {
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for occurrence in occurrences {
+        if !is_tag_or_child(&occurrence.name, old) {
+            continue;
+        }
+        let child: String = occurrence.name.chars().skip(old.chars().count()).collect();
+        let hash = if occurrence.in_frontmatter { "" } else { "#" };
+        changes
+            .entry(occurrence.location.uri)
+            .or_default()
+            .push(TextEdit {
+                range: occurrence.location.range,
+                new_text: format!("{}{}{}", hash, new, child),
+            });
+    }
+    changes
+}
+
+/// Whether `name` is the tag `tag` or one of its nested tags (`project/alpha` for `project`).
+/// Tags are case-insensitive.
+pub fn is_tag_or_child(name: &str, tag: &str) -> bool {
+    let name = name.to_lowercase();
+    let tag = tag.to_lowercase();
+    name == tag || name.starts_with(&format!("{}/", tag))
+}
+
+/// Completion items for the tags of the vault, most used first. Tags differing only by case are
+/// offered once, spelled as first found. Each item replaces `range`, the part of the tag typed
+/// after `#`.
+pub fn tag_completions(occurrences: &[TagOccurrence], range: Range) -> Vec<CompletionItem> {
+    let mut counts: HashMap<String, (&str, usize)> = HashMap::new();
+    for occurrence in occurrences {
+        counts
+            .entry(occurrence.name.to_lowercase())
+            .or_insert((&occurrence.name, 0))
+            .1 += 1;
+    }
+    let mut tags: Vec<(&str, usize)> = counts.into_values().collect();
+    tags.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    tags.into_iter()
+        .enumerate()
+        .map(|(rank, (name, count))| CompletionItem {
+            label: format!("#{}", name),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some(match count {
+                1 => "1 use".to_string(),
+                n => format!("{} uses", n),
+            }),
+            filter_text: Some(name.to_string()),
+            sort_text: Some(format!("{:05}", rank)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: name.to_string(),
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Hover text for a tag: how many notes use it, nested tags included.
+pub fn tag_hover(occurrences: &[TagOccurrence], tag: &str) -> String {
+    let matching: Vec<&TagOccurrence> = occurrences
+        .iter()
+        .filter(|o| is_tag_or_child(&o.name, tag))
+        .collect();
+    let mut notes: Vec<&Url> = matching.iter().map(|o| &o.location.uri).collect();
+    notes.sort();
+    notes.dedup();
+    let notes = match notes.len() {
+        1 => "1 note".to_string(),
+        n => format!("{} notes", n),
+    };
+    let uses = match matching.len() {
+        1 => "1 occurrence".to_string(),
+        n => format!("{} occurrences", n),
+    };
+    format!("**#{}**: used in {} ({})", tag, notes, uses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::ParsedNote;
+    use crate::position::PositionEncoding;
+
+    /// Renames a tag in a single note and returns the new text.
+    fn rename(text: &str, old: &str, new: &str) -> String {
+        let note = ParsedNote::parse(text, PositionEncoding::Utf16);
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let occurrences = note
+            .tags
+            .iter()
+            .map(|tag| TagOccurrence {
+                name: tag.value.name.clone(),
+                in_frontmatter: tag.value.in_frontmatter,
+                location: Location {
+                    uri: uri.clone(),
+                    range: tag.range,
+                },
+            })
+            .collect();
+        let edits = tag_rename_edits(occurrences, old, new)
+            .remove(&uri)
+            .unwrap_or_default();
+        note.apply_edits(&edits)
+    }
+
+    #[test]
+    fn renaming_rewrites_the_tags_but_not_the_frontmatter_keys() {
+        let text = "---\ntitle: project plan\ntags: [project, project/alpha, other]\n---\n\
+                    #project and #projects\n";
+        let renamed = rename(text, "project", "work");
+        assert_eq!(
+            renamed,
+            "---\ntitle: project plan\ntags: [work, work/alpha, other]\n---\n\
+             #work and #projects\n"
+        );
+        let note = ParsedNote::parse(&renamed, PositionEncoding::Utf16);
+        let yaml = note.frontmatter.unwrap().value.value.unwrap();
+        assert_eq!(yaml["title"], "project plan");
+        assert_eq!(
+            yaml["tags"],
+            serde_yaml::from_str::<serde_yaml::Value>("[work, work/alpha, other]").unwrap()
+        );
+    }
+
+    #[test]
+    fn renaming_a_tag_named_like_a_key_keeps_the_key() {
+        assert_eq!(
+            rename("---\ntags: [a]\n---\n", "a", "b"),
+            "---\ntags: [b]\n---\n"
+        );
+        assert_eq!(
+            rename("---\ntitle: a\ntag:\n  - a\n---\n", "a", "b"),
+            "---\ntitle: a\ntag:\n  - b\n---\n"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// src/wiki_links.rs
+
+use crate::blocks;
+use crate::headings;
+use crate::note::ParsedNote;
+use crate::position::LineIndex;
+
+/// A wiki‑link found somewhere in a document.
+/// Offsets are byte offsets into the line the link was found on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    /// Zero-based line index of the link.
+    pub line: usize,
+    /// Byte offset of the opening `[[`.
+    pub start: usize,
+    /// Byte offset just past the closing `]]`.
+    pub end: usize,
+    pub virtual_path: String,
+    /// Heading or block the link points at, from `[[virtual_path#Heading]]` or
+    /// `[[virtual_path#^block-id]]`.
+    pub anchor: Option<String>,
+    pub alias: Option<String>,
+}
+
+/// Scans a whole document and returns every wiki‑link of the form
+/// `[[virtual_path]]`, `[[virtual_path#Heading]]` or `[[virtual_path#Heading|alias]]`,
+/// in document order. `lines` is the line index of `text`.
+pub fn find_wiki_links(text: &str, lines: &LineIndex) -> Vec<WikiLink> {
+    lines
+        .lines(text)
+        .enumerate()
+        .flat_map(|(line_index, line)| find_wiki_links_in_line(line_index, line))
+        .collect()
+}
+
+/// Returns every wiki‑link on a single line.
+pub fn find_wiki_links_in_line(line_index: usize, line: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    let mut offset = 0;
+    while let Some(open_rel) = line[offset..].find("[[") {
+        let start = offset + open_rel;
+        let Some(close_rel) = line[start + 2..].find("]]") else {
+            break;
+        };
+        let end = start + 2 + close_rel;
+        let content = &line[start + 2..end];
+        // A nested "[[" means the first opening bracket was never closed.
+        if let Some(nested) = content.rfind("[[") {
+            offset = start + 2 + nested;
+            continue;
+        }
+        let mut parts = content.splitn(2, '|');
+        let target = parts.next().unwrap_or("");
+        let alias = parts.next().map(|a| a.trim().to_string());
+        let (virtual_path, anchor) = match target.split_once('#') {
+            Some((path, anchor)) => (path.trim(), Some(anchor.trim().to_string())),
+            None => (target.trim(), None),
+        };
+        if !virtual_path.is_empty() {
+            links.push(WikiLink {
+                line: line_index,
+                start,
+                end: end + 2,
+                virtual_path: virtual_path.to_string(),
+                anchor: anchor.filter(|a| !a.is_empty()),
+                alias,
+            });
+        }
+        offset = end + 2;
+    }
+    links
+}
+
+/// The part of a target note an anchor points at.
+pub struct AnchorTarget<'a> {
+    /// Line to jump to: the heading, or the first line of the block.
+    pub line: usize,
+    /// The lines of the heading's section, or of the block.
+    pub lines: Vec<&'a str>,
+}
+
+/// Resolves a `Heading` or `^block-id` anchor against the target note.
+pub fn resolve_anchor<'a>(note: &'a ParsedNote, anchor: &str) -> Option<AnchorTarget<'a>> {
+    match anchor.strip_prefix('^') {
+        Some(id) => {
+            let block = blocks::find_block(note, id)?;
+            let (start, _) = blocks::block_span(note, block);
+            Some(AnchorTarget {
+                line: start,
+                lines: blocks::block_lines(note, block),
+            })
+        }
+        None => {
+            let index = headings::find_heading(note, anchor)?;
+            Some(AnchorTarget {
+                line: note.headings[index].value.line,
+                lines: headings::section_lines(note, index),
+            })
+        }
+    }
+}
@@ -4,17 +4,24 @@ use futures::future::join_all;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs;
-use tower_lsp::lsp_types::{
-    Location, Position, Range, SymbolInformation, SymbolKind, WorkspaceSymbolParams,
-};
+use tower_lsp::lsp_types::{Location, Range, SymbolInformation, SymbolKind};
 use url::Url;
 
 use crate::db;
+use crate::note::ParsedNote;
+use crate::position::PositionEncoding;
 
-/// Asynchronously gathers workspace symbols from all files stored in the database.
+/// Asynchronously gathers workspace symbols (headings and `#tags`) from all files stored in the
+/// database.
 /// It uses the local path (the `path` field) rather than the virtual path.
 /// If a query string is provided, the symbols are filtered (case‑insensitive).
-pub async fn get_workspace_symbols(db: Arc<db::Database>, query: &str) -> Vec<SymbolInformation> {
+/// Heading ranges are expressed in the negotiated position encoding.
+#[allow(deprecated)] // `SymbolInformation::deprecated` has to be set even though it is unused.
+pub async fn get_workspace_symbols(
+    db: Arc<db::Database>,
+    query: &str,
+    encoding: PositionEncoding,
+) -> Vec<SymbolInformation> {
     let mut all_symbols = Vec::new();
 
     // Get all file infos from the DB.
@@ -30,7 +37,7 @@ pub async fn get_workspace_symbols(db: Arc<db::Database>, query: &str) -> Vec<Sy
     //   1. Use the local path stored in `info.path`.
     //   2. Convert it to a URI.
     //   3. Read the file content asynchronously.
-    //   4. Extract markdown headings as symbols.
+    //   4. Extract markdown headings and tags as symbols.
     let tasks = infos.into_iter().map(|info| {
         async move {
             let mut symbols = Vec::new();
@@ -50,32 +57,42 @@ pub async fn get_workspace_symbols(db: Arc<db::Database>, query: &str) -> Vec<Sy
             let content = fs::read_to_string(&local_path).await.ok();
 
             if let Some(content) = content {
-                // Extract markdown headings (lines starting with '#').
-                let headings = extract_headings(&content);
-                for (line, heading_text) in headings {
+                // Extract the markdown headings of the note.
+                let note = ParsedNote::parse(&content, encoding);
+                // The frontmatter title takes precedence over the database one.
+                let title = note.title().unwrap_or_else(|| info.title.clone());
+                for heading in note
+                    .headings
+                    .into_iter()
+                    .filter(|h| !h.value.text.is_empty())
+                {
                     let symbol = SymbolInformation {
-                        name: heading_text,
+                        name: heading.value.text,
                         // You might adjust the SymbolKind based on your needs.
                         kind: SymbolKind::STRING,
                         location: Location {
                             uri: uri.clone(),
-                            range: Range {
-                                start: Position {
-                                    line: line as u32,
-                                    character: 0,
-                                },
-                                end: Position {
-                                    line: line as u32,
-                                    character: 0,
-                                },
-                            },
+                            range: heading.range,
                         },
-                        container_name: Some(info.title.clone()),
+                        container_name: Some(title.clone()),
                         deprecated: None,
                         tags: None,
                     };
                     symbols.push(symbol);
                 }
+                for tag in note.tags {
+                    symbols.push(SymbolInformation {
+                        name: format!("#{}", tag.value.name),
+                        kind: SymbolKind::KEY,
+                        location: Location {
+                            uri: uri.clone(),
+                            range: tag.range,
+                        },
+                        container_name: Some(title.clone()),
+                        deprecated: None,
+                        tags: None,
+                    });
+                }
             } else {
                 // If the file cannot be read, create a fallback symbol at the file level.
                 symbols.push(SymbolInformation {
@@ -83,16 +100,7 @@ pub async fn get_workspace_symbols(db: Arc<db::Database>, query: &str) -> Vec<Sy
                     kind: SymbolKind::FILE,
                     location: Location {
                         uri: uri.clone(),
-                        range: Range {
-                            start: Position {
-                                line: 0,
-                                character: 0,
-                            },
-                            end: Position {
-                                line: 0,
-                                character: 0,
-                            },
-                        },
+                        range: Range::default(),
                     },
                     container_name: None,
                     deprecated: None,
@@ -116,20 +124,3 @@ pub async fn get_workspace_symbols(db: Arc<db::Database>, query: &str) -> Vec<Sy
 
     all_symbols
 }
-
-/// A simple helper that extracts markdown headings from the file content.
-/// It returns a vector of tuples: (line number, heading text).
-fn extract_headings(content: &str) -> Vec<(usize, String)> {
-    let mut headings = Vec::new();
-    for (i, line) in content.lines().enumerate() {
-        let trimmed = line.trim();
-        if trimmed.starts_with('#') {
-            // Remove all leading '#' characters and any extra whitespace.
-            let heading_text = trimmed.trim_start_matches('#').trim().to_string();
-            if !heading_text.is_empty() {
-                headings.push((i, heading_text));
-            }
-        }
-    }
-    headings
-}